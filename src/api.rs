@@ -0,0 +1,305 @@
+/* ===============================================================================
+Бот для анонимизации сообщений для чата.
+JSON API для интеграций. 19 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use std::{collections::HashMap, convert::TryFrom};
+use serde_json::{json, Value};
+use teloxide::prelude::*;
+use warp::{http::{header, StatusCode}, hyper::body::Bytes, reply::Response, Filter, Rejection, Reply};
+
+use crate::database as db;
+use crate::moderation::{self, Content};
+
+// Описание API в формате OpenAPI
+const OPENAPI: &str = include_str!("openapi.json");
+
+// Наибольший размер тела запроса
+const MAX_BODY: u64 = 64 * 1024;
+
+// Сообщений на странице по умолчанию и наибольшее
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 100;
+
+// Статусы, означающие принятое решение
+const DECIDED: [&str; 3] = ["approved", "rejected", "expired"];
+
+/// Маршруты API для сервера, принимающего обновления от Telegram. Доступ по токену чата,
+/// который выдаёт команда /apitoken, в заголовке "Authorization: Bearer токен"
+pub fn routes(bot: AutoSend<Bot>) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+   let auth = warp::header::optional::<String>("authorization");
+   let with_bot = warp::any().map(move || bot.clone());
+   let query = warp::query::<HashMap<String, String>>();
+   let body = warp::body::content_length_limit(MAX_BODY).and(warp::body::bytes());
+
+   let openapi = warp::get()
+   .and(warp::path!("api" / "openapi.json"))
+   .map(|| warp::reply::with_header(OPENAPI, header::CONTENT_TYPE, "application/json").into_response());
+
+   let chat = warp::get()
+   .and(warp::path!("api" / "v1" / "chat"))
+   .and(auth)
+   .and_then(chat);
+
+   let list = warp::get()
+   .and(warp::path!("api" / "v1" / "submissions"))
+   .and(auth)
+   .and(query)
+   .and_then(list);
+
+   let show = warp::get()
+   .and(warp::path!("api" / "v1" / "submissions" / i32))
+   .and(auth)
+   .and_then(show);
+
+   let submit = warp::post()
+   .and(warp::path!("api" / "v1" / "submissions"))
+   .and(auth)
+   .and(body)
+   .and_then(submit);
+
+   let decide = warp::post()
+   .and(warp::path!("api" / "v1" / "submissions" / i32 / "decision"))
+   .and(auth)
+   .and(with_bot)
+   .and(body)
+   .and_then(decide);
+
+   let decisions = warp::get()
+   .and(warp::path!("api" / "v1" / "decisions"))
+   .and(auth)
+   .and(query)
+   .and_then(decisions);
+
+   let stats = warp::get()
+   .and(warp::path!("api" / "v1" / "stats"))
+   .and(auth)
+   .and(query)
+   .and_then(stats);
+
+   openapi
+   .or(chat).unify()
+   .or(list).unify()
+   .or(show).unify()
+   .or(submit).unify()
+   .or(decide).unify()
+   .or(decisions).unify()
+   .or(stats).unify()
+}
+
+// Ответ в JSON
+fn reply(status: StatusCode, value: Value) -> Response {
+   warp::reply::with_status(warp::reply::json(&value), status).into_response()
+}
+
+// Ответ с ошибкой
+fn error(status: StatusCode, message: &str) -> Response {
+   reply(status, json!({ "error": message }))
+}
+
+// Находит чат по токену из заголовка Authorization
+async fn authorize(auth: Option<String>) -> Result<(String, i64), (StatusCode, String)> {
+   let token = auth.as_deref()
+   .and_then(|auth| auth.strip_prefix("Bearer "))
+   .map(str::trim)
+   .unwrap_or_default();
+   if token.is_empty() {
+      return Err((StatusCode::UNAUTHORIZED, String::from("Нужен заголовок 'Authorization: Bearer токен', токен выдаёт команда /apitoken")));
+   }
+   db::api_chat(token).await.ok_or_else(|| (StatusCode::UNAUTHORIZED, String::from("Токен недействителен")))
+}
+
+// Разбирает тело запроса в JSON
+fn parse_body(body: &Bytes) -> Result<Value, (StatusCode, String)> {
+   serde_json::from_slice(body).map_err(|e| (StatusCode::BAD_REQUEST, format!("Тело запроса должно быть в JSON: {}", e)))
+}
+
+// Смещение и размер страницы из параметров запроса
+fn page(query: &HashMap<String, String>) -> (i64, i64) {
+   let offset = query.get("offset").and_then(|v| v.parse().ok()).unwrap_or(0).max(0);
+   let limit = query.get("limit").and_then(|v| v.parse().ok()).unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+   (offset, limit)
+}
+
+// Сообщение в JSON. Автор не раскрывается
+async fn submission_json(submission: &db::Submission, created: &str, decided: Option<&str>) -> Value {
+   let (approvals, rejections) = db::votes(submission.id).await;
+   json!({
+      "id": submission.id,
+      "status": submission.status,
+      "mode": submission.mode,
+      "text": submission.text,
+      "html": submission.text_html(),
+      "notes": submission.notes,
+      "thread": submission.thread,
+      "reply_to": submission.reply_to,
+      "published_id": submission.published_id,
+      "poll": submission.poll.as_ref().map(|poll| json!({
+         "question": poll.question,
+         "options": poll.options,
+         "multiple": poll.multiple,
+         "correct": poll.correct,
+      })),
+      "media": submission.media.as_ref().map(|list| list.iter().map(ToString::to_string).collect::<Vec<_>>()),
+      "created": created,
+      "decided": decided,
+      "votes": { "approve": approvals, "reject": rejections },
+   })
+}
+
+// Список сообщений в JSON
+async fn submissions_json(list: Vec<(db::Submission, String, Option<String>)>) -> Value {
+   let mut items = Vec::with_capacity(list.len());
+   for (submission, created, decided) in list {
+      items.push(submission_json(&submission, &created, decided.as_deref()).await);
+   }
+   Value::from(items)
+}
+
+// Чат токена: название, настройки и число сообщений в очереди
+async fn chat(auth: Option<String>) -> Result<Response, Rejection> {
+   let (chat_name, _) = match authorize(auth).await {
+      Ok(chat) => chat,
+      Err((status, e)) => return Ok(error(status, &e)),
+   };
+
+   let settings: serde_json::Map<String, Value> = db::settings(&chat_name).await.describe().lines()
+   .map(|line| {
+      let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+      (String::from(key), Value::from(value))
+   })
+   .collect();
+   let pending = db::queue_counts(std::slice::from_ref(&chat_name)).await
+   .first()
+   .map_or(0, |(_, count)| *count);
+   Ok(reply(StatusCode::OK, json!({
+      "chat": chat_name,
+      "settings": settings,
+      "pending": pending,
+      "topics": db::topics(&chat_name).await.into_iter()
+         .map(|(thread, title)| json!({ "thread": thread, "title": title }))
+         .collect::<Vec<_>>(),
+   })))
+}
+
+// Сообщения чата, можно отобрать по статусу через запятую
+async fn list(auth: Option<String>, query: HashMap<String, String>) -> Result<Response, Rejection> {
+   let (chat_name, _) = match authorize(auth).await {
+      Ok(chat) => chat,
+      Err((status, e)) => return Ok(error(status, &e)),
+   };
+
+   let statuses: Vec<String> = query.get("status")
+   .map(|status| status.split(',').map(|s| String::from(s.trim())).filter(|s| !s.is_empty()).collect())
+   .unwrap_or_default();
+   let (offset, limit) = page(&query);
+   let list = db::submissions(&chat_name, None, &statuses, offset, limit).await;
+   Ok(reply(StatusCode::OK, submissions_json(list).await))
+}
+
+// Сообщение чата по номеру
+async fn show(id: i32, auth: Option<String>) -> Result<Response, Rejection> {
+   let (chat_name, _) = match authorize(auth).await {
+      Ok(chat) => chat,
+      Err((status, e)) => return Ok(error(status, &e)),
+   };
+
+   match db::submissions(&chat_name, Some(id), &[], 0, 1).await.pop() {
+      Some((submission, created, decided)) => Ok(reply(StatusCode::OK, submission_json(&submission, &created, decided.as_deref()).await)),
+      None => Ok(error(StatusCode::NOT_FOUND, "Сообщение не найдено")),
+   }
+}
+
+// Отправка сообщения интеграцией от имени администратора чата. Оно проходит те же проверки
+// и очистку, что и сообщения авторов, и попадает в очередь без предпросмотра
+async fn submit(auth: Option<String>, body: Bytes) -> Result<Response, Rejection> {
+   let (chat_name, admin_id) = match authorize(auth).await {
+      Ok(chat) => chat,
+      Err((status, e)) => return Ok(error(status, &e)),
+   };
+   let body = match parse_body(&body) {
+      Ok(body) => body,
+      Err((status, e)) => return Ok(error(status, &e)),
+   };
+
+   let text = match body["text"].as_str() {
+      Some(text) if !text.trim().is_empty() => String::from(text),
+      _ => return Ok(error(StatusCode::BAD_REQUEST, "Нужен непустой текст в поле text")),
+   };
+   let thread = match &body["thread"] {
+      Value::Null => None,
+      thread => match thread.as_i64().and_then(|thread| i32::try_from(thread).ok()) {
+         Some(thread) => Some(thread),
+         None => return Ok(error(StatusCode::BAD_REQUEST, "Поле thread должно быть номером темы")),
+      },
+   };
+
+   match moderation::submit(&chat_name, admin_id, &Content::Text(text, Vec::new()), thread).await {
+      Ok((id, message)) => Ok(reply(StatusCode::CREATED, json!({ "id": id, "status": "pending", "message": message }))),
+      Err(e) => Ok(error(StatusCode::UNPROCESSABLE_ENTITY, &e)),
+   }
+}
+
+// Решение по ожидающему сообщению, учитывается как голос администратора чата
+async fn decide(id: i32, auth: Option<String>, bot: AutoSend<Bot>, body: Bytes) -> Result<Response, Rejection> {
+   let (chat_name, admin_id) = match authorize(auth).await {
+      Ok(chat) => chat,
+      Err((status, e)) => return Ok(error(status, &e)),
+   };
+   let body = match parse_body(&body) {
+      Ok(body) => body,
+      Err((status, e)) => return Ok(error(status, &e)),
+   };
+
+   let approve = match body["verdict"].as_str() {
+      Some("approve") => true,
+      Some("reject") => false,
+      _ => return Ok(error(StatusCode::BAD_REQUEST, "Поле verdict должно быть approve или reject")),
+   };
+   let submission = match db::submission(id).await {
+      Some(submission) if submission.chat_name == chat_name => submission,
+      _ => return Ok(error(StatusCode::NOT_FOUND, "Сообщение не найдено")),
+   };
+   let card_id = match (submission.status.as_str(), submission.card_id) {
+      ("pending", Some(card_id)) => card_id,
+      ("pending", None) => return Ok(error(StatusCode::CONFLICT, "Сообщение ещё не направлено на модерацию")),
+      _ => return Ok(error(StatusCode::CONFLICT, "Решение уже принято")),
+   };
+
+   let by = format!("интеграция через API, {}", chrono::Utc::now().format("%d.%m.%Y %H:%M UTC"));
+   let card = (submission.card_chat.unwrap_or(admin_id), card_id);
+   let message = moderation::cast_vote(&bot, admin_id, card, &submission, admin_id, approve, by).await;
+   let status = db::submission(id).await.map_or(submission.status, |submission| submission.status);
+   Ok(reply(StatusCode::OK, json!({ "id": id, "status": status, "message": message })))
+}
+
+// Сообщения, по которым принято решение, начиная с новых
+async fn decisions(auth: Option<String>, query: HashMap<String, String>) -> Result<Response, Rejection> {
+   let (chat_name, _) = match authorize(auth).await {
+      Ok(chat) => chat,
+      Err((status, e)) => return Ok(error(status, &e)),
+   };
+
+   let statuses: Vec<String> = DECIDED.iter().map(|s| String::from(*s)).collect();
+   let (offset, limit) = page(&query);
+   let list = db::submissions(&chat_name, None, &statuses, offset, limit).await;
+   Ok(reply(StatusCode::OK, submissions_json(list).await))
+}
+
+// Число сообщений по статусам за последние дни и за всё время
+async fn stats(auth: Option<String>, query: HashMap<String, String>) -> Result<Response, Rejection> {
+   let (chat_name, _) = match authorize(auth).await {
+      Ok(chat) => chat,
+      Err((status, e)) => return Ok(error(status, &e)),
+   };
+
+   let days = query.get("days").and_then(|v| v.parse().ok()).unwrap_or(30).clamp(1, 3650);
+   let statuses: Vec<Value> = db::stats(&chat_name, days).await.into_iter()
+   .map(|(status, recent, total)| json!({ "status": status, "recent": recent, "total": total }))
+   .collect();
+   Ok(reply(StatusCode::OK, json!({ "days": days, "statuses": statuses })))
+}
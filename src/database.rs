@@ -8,15 +8,57 @@ Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
 =============================================================================== */
 
 use once_cell::sync::{OnceCell};
-use arraylib::iter::IteratorExt;
-use teloxide::{
-   types::{InlineKeyboardMarkup, InlineKeyboardButton, },
-};
+use teloxide::utils::html;
+use sha2::{Sha256, Digest};
+use rand::{Rng, distributions::Alphanumeric};
+use std::{env, time::Instant};
+use tokio_postgres::{types::ToSql, Error, Row};
+use crate::filters::{self, Rule};
+use crate::picker::Order;
+use crate::poll::Poll;
+use crate::media::{self, Media};
+use crate::metrics;
+use crate::settings::Settings;
 
 // Клиент БД
-pub static DB: OnceCell<tokio_postgres::Client> = OnceCell::new();
+pub static DB: OnceCell<Client> = OnceCell::new();
 
-/// Создаёт таблицу, если её ещё не существует
+/// Клиент БД, учитывающий время запросов в метриках
+pub struct Client(tokio_postgres::Client);
+
+// Выполняет запрос и учитывает его время
+macro_rules! timed {
+   ($query:expr) => {{
+      let started = Instant::now();
+      let res = $query.await;
+      metrics::DB_QUERY.observe(started.elapsed());
+      res
+   }};
+}
+
+impl Client {
+   pub fn new(client: tokio_postgres::Client) -> Self {
+      Self(client)
+   }
+
+   pub async fn execute(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<u64, Error> {
+      timed!(self.0.execute(query, params))
+   }
+
+   pub async fn query(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, Error> {
+      timed!(self.0.query(query, params))
+   }
+
+   pub async fn query_one(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Row, Error> {
+      timed!(self.0.query_one(query, params))
+   }
+
+   pub async fn query_opt(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Option<Row>, Error> {
+      timed!(self.0.query_opt(query, params))
+   }
+}
+
+/// Создаёт таблицы, если их ещё не существует
 pub async fn check_database() {
    // Получаем клиента БД
    let client = DB.get().unwrap();
@@ -34,14 +76,252 @@ pub async fn check_database() {
          errors         INTEGER        NOT NULL
       )", &[]).await.unwrap();
    }
+
+   // Сообщения, направленные на модерацию
+   client.execute("CREATE TABLE IF NOT EXISTS submissions (
+      PRIMARY KEY (id),
+      id             SERIAL,
+      chat_name      VARCHAR(100)   NOT NULL,
+      author_id      BIGINT         NOT NULL,
+      text           TEXT           NOT NULL,
+      status         VARCHAR(20)    NOT NULL,
+      created        TIMESTAMP      NOT NULL
+   )", &[]).await.unwrap();
+
+   // Заблокированные авторы, вместо кода пользователя хранится только хеш
+   client.execute("CREATE TABLE IF NOT EXISTS bans (
+      PRIMARY KEY (id),
+      id             SERIAL,
+      chat_name      VARCHAR(100)   NOT NULL,
+      author_hash    VARCHAR(64)    NOT NULL,
+      created        TIMESTAMP      NOT NULL,
+      until          TIMESTAMP
+   )", &[]).await.unwrap();
+
+   // Правила фильтрации сообщений
+   client.execute("CREATE TABLE IF NOT EXISTS filters (
+      PRIMARY KEY (id),
+      id             SERIAL,
+      chat_name      VARCHAR(100)   NOT NULL,
+      action         VARCHAR(10)    NOT NULL,
+      kind           VARCHAR(10)    NOT NULL,
+      pattern        TEXT           NOT NULL
+   )", &[]).await.unwrap();
+
+   // Настройки чатов парами название-значение
+   client.execute("CREATE TABLE IF NOT EXISTS settings (
+      PRIMARY KEY (chat_name, key),
+      chat_name      VARCHAR(100)   NOT NULL,
+      key            VARCHAR(20)    NOT NULL,
+      value          TEXT           NOT NULL
+   )", &[]).await.unwrap();
+
+   // Код опубликованного в чате сообщения, чтобы его можно было удалить
+   client.execute("ALTER TABLE submissions ADD COLUMN IF NOT EXISTS published_id INTEGER", &[]).await.unwrap();
+
+   // Код сообщения у администратора, чтобы его можно было исправить
+   client.execute("ALTER TABLE submissions ADD COLUMN IF NOT EXISTS card_id INTEGER", &[]).await.unwrap();
+
+   // Очередь доставки модератору: пометки для него, режим модерации и время доставки,
+   // пустое время означает ожидание, пока наберётся пакет сообщений
+   client.execute("ALTER TABLE submissions
+      ADD COLUMN IF NOT EXISTS notes TEXT NOT NULL DEFAULT '',
+      ADD COLUMN IF NOT EXISTS mode VARCHAR(10) NOT NULL DEFAULT 'manual',
+      ADD COLUMN IF NOT EXISTS queued BOOLEAN NOT NULL DEFAULT FALSE,
+      ADD COLUMN IF NOT EXISTS deliver_at TIMESTAMP", &[]).await.unwrap();
+
+   // Текст с разметкой в HTML, если автор её использовал
+   client.execute("ALTER TABLE submissions ADD COLUMN IF NOT EXISTS html TEXT", &[]).await.unwrap();
+
+   // Варианты ответа, вид и правильный ответ, если сообщение - опрос, вопрос хранится как текст
+   client.execute("ALTER TABLE submissions
+      ADD COLUMN IF NOT EXISTS poll_options TEXT[],
+      ADD COLUMN IF NOT EXISTS poll_kind VARCHAR(10),
+      ADD COLUMN IF NOT EXISTS poll_correct INTEGER", &[]).await.unwrap();
+
+   // Вложения в виде "вид:код файла" и коды остальных опубликованных сообщений альбома
+   client.execute("ALTER TABLE submissions
+      ADD COLUMN IF NOT EXISTS media TEXT[],
+      ADD COLUMN IF NOT EXISTS published_more INTEGER[]", &[]).await.unwrap();
+
+   // Сообщение чата, на которое отвечает автор
+   client.execute("ALTER TABLE submissions ADD COLUMN IF NOT EXISTS reply_to INTEGER", &[]).await.unwrap();
+
+   // Части альбомов, пока автор выбирает чат
+   client.execute("CREATE TABLE IF NOT EXISTS album_parts (
+      PRIMARY KEY (media_group_id, message_id),
+      media_group_id VARCHAR(50)    NOT NULL,
+      message_id     INTEGER        NOT NULL,
+      media          TEXT           NOT NULL,
+      caption        TEXT           NOT NULL,
+      entities       TEXT           NOT NULL,
+      created        TIMESTAMP      NOT NULL
+   )", &[]).await.unwrap();
+
+   // Число опубликованных в чат сообщений, для сортировки по популярности
+   client.execute("ALTER TABLE chats ADD COLUMN IF NOT EXISTS uses INTEGER NOT NULL DEFAULT 0", &[]).await.unwrap();
+
+   // Код постоянной ссылки на чат для предварительного выбора его для отправки
+   client.execute("ALTER TABLE chats ADD COLUMN IF NOT EXISTS link VARCHAR(32) UNIQUE", &[]).await.unwrap();
+
+   // Приглашения в чаты, не видимые в общем списке
+   client.execute("CREATE TABLE IF NOT EXISTS invites (
+      PRIMARY KEY (id),
+      id             SERIAL,
+      chat_name      VARCHAR(100)   NOT NULL,
+      token          VARCHAR(32)    NOT NULL UNIQUE,
+      created        TIMESTAMP      NOT NULL
+   )", &[]).await.unwrap();
+
+   // Пользователи, принявшие приглашение, доступ пропадает при его отзыве
+   client.execute("CREATE TABLE IF NOT EXISTS access (
+      PRIMARY KEY (user_id, invite_id),
+      user_id        BIGINT         NOT NULL,
+      invite_id      INTEGER        NOT NULL REFERENCES invites (id) ON DELETE CASCADE
+   )", &[]).await.unwrap();
+
+   // Чат, заранее выбранный пользователем для отправки
+   client.execute("CREATE TABLE IF NOT EXISTS users (
+      PRIMARY KEY (user_id),
+      user_id        BIGINT         NOT NULL,
+      target         VARCHAR(100)   NOT NULL
+   )", &[]).await.unwrap();
+
+   // Последний выбранный пользователем чат, заранее выбранного при этом может не быть
+   client.execute("ALTER TABLE users
+      ALTER COLUMN target DROP NOT NULL,
+      ADD COLUMN IF NOT EXISTS last_chat VARCHAR(100)", &[]).await.unwrap();
+
+   // Темы форума, в которые можно отправлять сообщения
+   client.execute("CREATE TABLE IF NOT EXISTS topics (
+      PRIMARY KEY (chat_name, thread_id),
+      chat_name      VARCHAR(100)   NOT NULL,
+      thread_id      INTEGER        NOT NULL,
+      title          VARCHAR(100)   NOT NULL
+   )", &[]).await.unwrap();
+
+   // Выбранная автором тема форума
+   client.execute("ALTER TABLE submissions ADD COLUMN IF NOT EXISTS thread_id INTEGER", &[]).await.unwrap();
+
+   // Группа модераторов чата, без неё сообщения на модерацию приходят администратору в личку
+   client.execute("ALTER TABLE chats ADD COLUMN IF NOT EXISTS modchat BIGINT", &[]).await.unwrap();
+
+   // Чат, куда отправлено сообщение на модерацию, и время решения по нему
+   client.execute("ALTER TABLE submissions
+      ADD COLUMN IF NOT EXISTS card_chat BIGINT,
+      ADD COLUMN IF NOT EXISTS decided_at TIMESTAMP", &[]).await.unwrap();
+
+   // Время отправки на модерацию, от него отсчитывается срок голосования
+   client.execute("ALTER TABLE submissions ADD COLUMN IF NOT EXISTS card_at TIMESTAMP", &[]).await.unwrap();
+
+   // Голоса модераторов, каждый может изменить свой голос до решения
+   client.execute("CREATE TABLE IF NOT EXISTS votes (
+      PRIMARY KEY (submission_id, voter_id),
+      submission_id  INTEGER        NOT NULL REFERENCES submissions (id) ON DELETE CASCADE,
+      voter_id       BIGINT         NOT NULL,
+      approve        BOOLEAN        NOT NULL
+   )", &[]).await.unwrap();
+
+   // Отметки о напоминании модераторам и передаче сообщения запасным модераторам
+   client.execute("ALTER TABLE submissions
+      ADD COLUMN IF NOT EXISTS reminded BOOLEAN NOT NULL DEFAULT FALSE,
+      ADD COLUMN IF NOT EXISTS escalated BOOLEAN NOT NULL DEFAULT FALSE", &[]).await.unwrap();
+
+   // Код ссылки, по которой запасные модераторы подключаются к чату
+   client.execute("ALTER TABLE chats ADD COLUMN IF NOT EXISTS backup_link VARCHAR(32) UNIQUE", &[]).await.unwrap();
+
+   // Запасные модераторы, им передаются сообщения, не рассмотренные вовремя
+   client.execute("CREATE TABLE IF NOT EXISTS backups (
+      PRIMARY KEY (chat_name, user_id),
+      chat_name      VARCHAR(100)   NOT NULL,
+      user_id        BIGINT         NOT NULL,
+      name           VARCHAR(100)   NOT NULL
+   )", &[]).await.unwrap();
+
+   // Хеш токена для доступа интеграций к чату через API, сам токен не хранится
+   client.execute("ALTER TABLE chats ADD COLUMN IF NOT EXISTS api_token VARCHAR(64) UNIQUE", &[]).await.unwrap();
+
+   // Адреса для уведомлений о событиях модерации и секреты для их подписи
+   client.execute("CREATE TABLE IF NOT EXISTS hooks (
+      id             SERIAL         PRIMARY KEY,
+      chat_name      VARCHAR(100)   NOT NULL,
+      url            TEXT           NOT NULL,
+      secret         VARCHAR(64)    NOT NULL,
+      events         VARCHAR(20)[]  NOT NULL
+   )", &[]).await.unwrap();
+
+   // Журнал доставки уведомлений, неудачные повторяются в next_at
+   client.execute("CREATE TABLE IF NOT EXISTS hook_deliveries (
+      id             SERIAL         PRIMARY KEY,
+      hook_id        INTEGER        NOT NULL REFERENCES hooks (id) ON DELETE CASCADE,
+      event          VARCHAR(20)    NOT NULL,
+      payload        TEXT           NOT NULL,
+      status         VARCHAR(10)    NOT NULL DEFAULT 'pending',
+      attempts       INTEGER        NOT NULL DEFAULT 0,
+      next_at        TIMESTAMP      NOT NULL DEFAULT NOW(),
+      response_code  INTEGER,
+      error          TEXT,
+      created        TIMESTAMP      NOT NULL DEFAULT NOW()
+   )", &[]).await.unwrap();
+
+   // Код автора хранится, только пока он нужен для отзыва сообщения, для блокировок остаётся хеш
+   client.execute("ALTER TABLE submissions
+      ALTER COLUMN author_id DROP NOT NULL,
+      ADD COLUMN IF NOT EXISTS author_hash VARCHAR(64)", &[]).await.unwrap();
+   let rows = client.query("SELECT id, chat_name, author_id FROM submissions WHERE author_hash IS NULL AND author_id IS NOT NULL", &[]).await.unwrap();
+   for row in rows {
+      let hash = author_hash(row.get(1), row.get(2));
+      client.execute("UPDATE submissions SET author_hash = $2::VARCHAR(64) WHERE id = $1::INTEGER", &[&row.get::<_, i32>(0), &hash]).await.unwrap();
+   }
+
+   // Выбор автора публиковать без звука, если он отличается от настройки чата
+   client.execute("ALTER TABLE submissions ADD COLUMN IF NOT EXISTS silent BOOLEAN", &[]).await.unwrap();
+
+   // Чаты, забытые после ошибок отправки, и их прежние администраторы. Данные таких чатов
+   // сохраняются, пока чат не зарегистрирует кто-то другой или сам администратор не удалит их
+   client.execute("CREATE TABLE IF NOT EXISTS dormant (
+      PRIMARY KEY (chat_name),
+      chat_name      VARCHAR(100)   NOT NULL,
+      user_id        BIGINT         NOT NULL,
+      since          TIMESTAMP      NOT NULL
+   )", &[]).await.unwrap();
+}
+
+// Части запроса, удаляющие данные чатов из подзапроса gone в других таблицах
+const CHAT_DATA: &[&str] = &[
+   "DELETE FROM hooks WHERE chat_name IN (SELECT chat_name FROM gone)",
+   "DELETE FROM bans WHERE chat_name IN (SELECT chat_name FROM gone)",
+   "DELETE FROM submissions WHERE chat_name IN (SELECT chat_name FROM gone)",
+   "DELETE FROM filters WHERE chat_name IN (SELECT chat_name FROM gone)",
+   "DELETE FROM settings WHERE chat_name IN (SELECT chat_name FROM gone)",
+   "DELETE FROM invites WHERE chat_name IN (SELECT chat_name FROM gone)",
+   // Одну строку нельзя изменить дважды за запрос, поэтому оба столбца в одной части
+   "UPDATE users SET target = CASE WHEN target IN (SELECT chat_name FROM gone) THEN NULL ELSE target END,
+      last_chat = CASE WHEN last_chat IN (SELECT chat_name FROM gone) THEN NULL ELSE last_chat END
+      WHERE target IN (SELECT chat_name FROM gone) OR last_chat IN (SELECT chat_name FROM gone)",
+   "DELETE FROM topics WHERE chat_name IN (SELECT chat_name FROM gone)",
+   "DELETE FROM backups WHERE chat_name IN (SELECT chat_name FROM gone)",
+];
+
+// Запрос, удаляющий данные чатов, которые выбирает подзапрос gone, целиком или никак
+fn drop_chat_data(gone: &str) -> String {
+   let parts: String = CHAT_DATA.iter().enumerate().map(|(i, part)| format!(", drop_{} AS ({})", i, part)).collect();
+   format!("WITH {}{} SELECT chat_name FROM gone", gone, parts)
 }
 
 /// Регистрация чата для пользователя
 pub async fn register(user_id: i64, chat_name: String) {
    let client = DB.get().unwrap();
 
-   // Удалим прежнюю информацию, если пользователь уже регистрировал чат
-   unregister(user_id).await;
+   // Прежний чат пользователя, если он был, забываем, но его данные сохраняем
+   deactivate(user_id).await;
+
+   // Данные, оставшиеся от другого администратора этого чата, новому не достаются
+   let query = drop_chat_data("dormant_gone AS (DELETE FROM dormant WHERE chat_name = $2::VARCHAR(100) RETURNING chat_name, user_id),
+      gone AS (SELECT chat_name FROM dormant_gone WHERE user_id <> $1::BIGINT)");
+   if let Err(e) = client.execute(query.as_str(), &[&user_id, &chat_name]).await {
+      log::error!("db_register 2 ({}, {}): {}", user_id, chat_name, e);
+   }
 
    // Добавляем новую запись, при ошибке сообщение в лог
    if let Err(e) = client.execute("INSERT INTO chats (user_id, chat_name, last_use, errors) VALUES ($1::BIGINT, $2::VARCHAR(100), NOW(), 0)", &[&user_id, &chat_name]).await {
@@ -49,13 +329,33 @@ pub async fn register(user_id: i64, chat_name: String) {
    }
 }
 
-/// Удаление инормации о пользователе
-pub async fn unregister(user_id: i64) {
+/// Удаление по просьбе пользователя его чата вместе со всеми данными, в том числе
+/// забытых ранее чатов. Возвращает названия удалённых чатов
+pub async fn unregister(user_id: i64) -> Vec<String> {
+   let client = DB.get().unwrap();
+   let query = drop_chat_data("active AS (DELETE FROM chats WHERE user_id = $1::BIGINT RETURNING chat_name),
+      inactive AS (DELETE FROM dormant WHERE user_id = $1::BIGINT RETURNING chat_name),
+      gone AS (SELECT chat_name FROM active UNION SELECT chat_name FROM inactive)");
+
+   // Выполняем запрос для удаления записей, при ошибке сообщение в лог
+   match client.query(query.as_str(), &[&user_id]).await {
+      Ok(rows) => rows.into_iter().map(|row| row.get(0)).collect(),
+      Err(e) => {
+         log::error!("db_unregister({}): {}", user_id, e);
+         Vec::new()
+      }
+   }
+}
+
+/// Забывает чат пользователя без удаления его данных: блокировок, правил, настроек,
+/// адресов уведомлений и ждущих решения сообщений
+pub async fn deactivate(user_id: i64) {
    let client = DB.get().unwrap();
-   
-   // Выполняем запрос для удаления записи, при ошибке сообщение в лог
-   if let Err(e) = client.execute("DELETE FROM chats WHERE user_id = $1::BIGINT", &[&user_id]).await {
-      log::error!("db_unregister({}): {}", user_id, e);
+   let query = "WITH gone AS (DELETE FROM chats WHERE user_id = $1::BIGINT RETURNING chat_name, user_id)
+      INSERT INTO dormant (chat_name, user_id, since) SELECT chat_name, user_id, NOW() FROM gone
+      ON CONFLICT (chat_name) DO UPDATE SET user_id = EXCLUDED.user_id, since = EXCLUDED.since";
+   if let Err(e) = client.execute(query, &[&user_id]).await {
+      log::error!("deactivate({}): {}", user_id, e);
    }
 }
 
@@ -79,31 +379,57 @@ pub async fn user_id(chat_name: &String) -> Option<i64> {
    }
 }
 
-/// Возвращает список кнопок с чатами
-pub async fn chats_markup() -> InlineKeyboardMarkup {
+/// Возвращает чат, куда направляются сообщения на модерацию: группу модераторов
+/// или, если она не задана, личку администратора
+pub async fn moderation_chat(chat_name: &str) -> Option<i64> {
    let client = DB.get().unwrap();
-   match client.query("SELECT chat_name FROM chats", &[]).await {
-      Ok(rows) => {
-         // Создадим кнопки
-         let mut buttons: Vec<InlineKeyboardButton> = rows.into_iter()
-         .map(|row| (InlineKeyboardButton::callback(row.get(0), row.get(0)))).collect();
-
-         // Последняя непарная кнопка, если есть
-         let last = if buttons.len() % 2 == 1 { buttons.pop() } else { None };
-
-         // Поделим по две в ряд
-         let markup = buttons.into_iter().array_chunks::<[_; 2]>()
-         .fold(InlineKeyboardMarkup::default(), |acc, [left, right]| acc.append_row(vec![left, right]));
-
-         // Добавляем последнюю непарную кнопку, если есть, а затем возвращаем результат
-         if let Some(last_button) = last {
-            markup.append_row(vec![last_button])
-         } else {
-            markup
-         }
+   match client.query_opt("SELECT COALESCE(modchat, user_id) FROM chats WHERE chat_name = $1::VARCHAR(100)", &[&chat_name]).await {
+      Ok(row) => row.map(|row| row.get(0)),
+      Err(e) => {
+         log::error!("moderation_chat({}): {}", chat_name, e);
+         None
+      }
+   }
+}
 
-      },
-      _ => InlineKeyboardMarkup::default(),
+/// Задаёт группу модераторов для чата администратора, пустая - модерация в личке.
+/// Возвращает успешность
+pub async fn set_modchat(user_id: i64, modchat: Option<i64>) -> bool {
+   let client = DB.get().unwrap();
+   match client.execute("UPDATE chats SET modchat = $2::BIGINT WHERE user_id = $1::BIGINT", &[&user_id, &modchat]).await {
+      Ok(cnt) => cnt > 0,
+      Err(e) => {
+         log::error!("set_modchat({}): {}", user_id, e);
+         false
+      }
+   }
+}
+
+/// Возвращает названия видимых всем чатов, содержащие строку поиска, в указанном порядке
+/// начиная с offset, и общее число найденных
+pub async fn chats(search: &str, order: Order, offset: i64, limit: i64) -> (Vec<String>, i64) {
+   let client = DB.get().unwrap();
+
+   // Знаки шаблона в строке поиска должны означать сами себя
+   let pattern = format!("%{}%", search.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+   let order_by = match order {
+      Order::Popular => "uses DESC, chat_name",
+      Order::Recent => "last_use DESC, chat_name",
+   };
+
+   // Чаты, скрытые настройкой видимости, в список не попадают
+   let query = format!("SELECT chat_name, COUNT(*) OVER () FROM chats WHERE chat_name ILIKE $1::VARCHAR
+      AND NOT EXISTS (SELECT 1 FROM settings WHERE settings.chat_name = chats.chat_name AND key = 'visibility' AND value <> 'public')
+      ORDER BY {} OFFSET $2::BIGINT LIMIT $3::BIGINT", order_by);
+   match client.query(query.as_str(), &[&pattern, &offset, &limit]).await {
+      Ok(rows) => {
+         let total = rows.first().map_or(0, |row| row.get(1));
+         (rows.into_iter().map(|row| row.get(0)).collect(), total)
+      }
+      Err(e) => {
+         log::error!("chats({}): {}", search, e);
+         (Vec::new(), 0)
+      }
    }
 }
 
@@ -111,6 +437,7 @@ pub async fn chats_markup() -> InlineKeyboardMarkup {
 /// Функция должна вызываться при каждой ошибке отправки сообщения админу или в чат
 pub async fn error_happened(user_id: i64) {
    let client = DB.get().unwrap();
+   metrics::ERRORS.inc();
 
    // Увеличиваем счётчик ошибок
    if let Err(e) = client.execute("UPDATE chats SET errors = errors + 1 WHERE user_id = $1::BIGINT", &[&user_id]).await {
@@ -122,22 +449,1090 @@ pub async fn error_happened(user_id: i64) {
    let res = client.query_one("SELECT errors FROM chats WHERE user_id = $1::BIGINT", &[&user_id]).await;
    match res {
       Ok(data) => {
-         // Если ошибок слишком много, забываем чат
+         // Если ошибок слишком много, забываем чат, сохраняя его данные
          let cnt: i32 = data.get(0);
-         if cnt > 3 {unregister(user_id).await;}
+         if cnt > 3 {
+            metrics::UNREGISTRATIONS.inc();
+            deactivate(user_id).await;
+         }
       }
       // При ошибке сообщаем в лог и выходим
       Err(e) => log::error!("error_happened 2 ({}): {}", user_id, e),
    }
 }
 
-/// Обнуляет счётчик ошибок отправки сообщений
+/// Обнуляет счётчик ошибок отправки сообщений и отмечает использование чата
 /// Функция должна вызываеться после каждой успешной попытки записи в чат, но не при
 /// успешной отправке сообщения админу, иначе это сбросит более приоритетный счётчик
 /// ошибок в чат
 pub async fn successful_sent(user_id: i64) {
    let client = DB.get().unwrap();
-   if let Err(e) = client.execute("UPDATE chats SET errors = 0 WHERE user_id = $1::BIGINT", &[&user_id]).await {
+   if let Err(e) = client.execute("UPDATE chats SET errors = 0, last_use = NOW(), uses = uses + 1 WHERE user_id = $1::BIGINT", &[&user_id]).await {
       log::error!("successful_sent({}): {}", user_id, e);
    };
-}
\ No newline at end of file
+}
+
+/// Сообщение, направленное на модерацию
+pub struct Submission {
+   pub id: i32,
+   pub chat_name: String,
+   // Код автора, после окончательного решения и срока отзыва забывается
+   pub author_id: Option<i64>,
+   // Хеш автора для блокировки
+   pub author_hash: String,
+   pub text: String,
+   pub status: String,
+   pub published_id: Option<i32>,
+   pub card_id: Option<i32>,
+   pub notes: String,
+   pub mode: String,
+   pub formatted: Option<String>,
+   pub poll: Option<Poll>,
+   pub media: Option<Vec<Media>>,
+   pub reply_to: Option<i32>,
+   pub thread: Option<i32>,
+   pub card_chat: Option<i64>,
+   // Публикация без звука по выбору автора, None - как в настройках чата
+   pub silent: Option<bool>,
+}
+
+impl Submission {
+   /// Сообщение в HTML для предпросмотра и модератора: для опроса вопрос с вариантами ответа,
+   /// для вложений их описание и подпись
+   pub fn html(&self) -> String {
+      match (&self.poll, &self.media) {
+         (Some(poll), _) => poll.html(),
+         (None, Some(list)) if self.text.is_empty() => media::describe(list),
+         (None, Some(list)) => format!("{}\n{}", media::describe(list), self.text_html()),
+         (None, None) => self.text_html(),
+      }
+   }
+
+   /// Текст или подпись в HTML, как они будут опубликованы
+   pub fn text_html(&self) -> String {
+      match &self.formatted {
+         Some(formatted) => formatted.clone(),
+         None => html::escape(&self.text),
+      }
+   }
+}
+
+// Поля сообщения для запросов
+const SUBMISSION_FIELDS: &str = "id, chat_name, author_id, text, status, published_id, card_id, notes, mode, html, poll_options, poll_kind, poll_correct, media, reply_to, thread_id, card_chat, author_hash, silent";
+
+impl From<tokio_postgres::Row> for Submission {
+   fn from(row: tokio_postgres::Row) -> Self {
+      Self {
+         id: row.get(0),
+         chat_name: row.get(1),
+         author_id: row.get(2),
+         text: row.get(3),
+         status: row.get(4),
+         published_id: row.get(5),
+         card_id: row.get(6),
+         notes: row.get(7),
+         mode: row.get(8),
+         formatted: row.get(9),
+         poll: row.get::<_, Option<Vec<String>>>(10).map(|options| Poll {
+            question: row.get(3),
+            options,
+            multiple: row.get::<_, Option<String>>(11).as_deref() == Some("multiple"),
+            correct: row.get(12),
+         }),
+         media: row.get::<_, Option<Vec<String>>>(13)
+         .map(|list| list.iter().filter_map(|s| Media::parse(s)).collect()),
+         reply_to: row.get(14),
+         thread: row.get(15),
+         card_chat: row.get(16),
+         author_hash: row.get::<_, Option<String>>(17).unwrap_or_default(),
+         silent: row.get(18),
+      }
+   }
+}
+
+/// Содержимое нового сообщения: текст или подпись, разметка, опрос, вложения
+/// и сообщение чата, на которое оно отвечает
+pub struct Draft {
+   pub text: String,
+   pub html: Option<String>,
+   pub poll: Option<Poll>,
+   pub media: Option<Vec<Media>>,
+   pub reply_to: Option<i32>,
+}
+
+/// Сохраняет черновик сообщения и возвращает его номер
+pub async fn new_submission(chat_name: &str, author_id: i64, draft: &Draft, notes: &str, mode: &str) -> Option<i32> {
+   let client = DB.get().unwrap();
+   let poll = draft.poll.as_ref();
+   let options = poll.map(|poll| &poll.options);
+   let kind = poll.map(Poll::kind);
+   let correct = poll.and_then(|poll| poll.correct);
+   let media = draft.media.as_ref().map(|list| list.iter().map(Media::to_string).collect::<Vec<_>>());
+   let hash = author_hash(chat_name, author_id);
+   let res = client.query_one("INSERT INTO submissions (chat_name, author_id, text, html, status, created, notes, mode, poll_options, poll_kind, poll_correct, media, reply_to, author_hash) VALUES ($1::VARCHAR(100), $2::BIGINT, $3::TEXT, $4::TEXT, 'draft', NOW(), $5::TEXT, $6::VARCHAR(10), $7::TEXT[], $8::VARCHAR(10), $9::INTEGER, $10::TEXT[], $11::INTEGER, $12::VARCHAR(64)) RETURNING id", &[&chat_name, &author_id, &draft.text, &draft.html, &notes, &mode, &options, &kind, &correct, &media, &draft.reply_to, &hash]).await;
+   match res {
+      Ok(data) => Some(data.get(0)),
+      Err(e) => {
+         log::error!("new_submission({}): {}", chat_name, e);
+         None
+      }
+   }
+}
+
+/// Ставит черновик, подтверждённый автором, в очередь доставки с указанной задержкой,
+/// пустая задержка означает ожидание пакета. Возвращает успешность
+pub async fn confirm(id: i32, delay: Option<u32>) -> bool {
+   let client = DB.get().unwrap();
+   let delay = delay.map(i64::from);
+   match client.execute("UPDATE submissions SET status = 'pending', created = NOW(), queued = TRUE, deliver_at = NOW() + $2::BIGINT * INTERVAL '1 second' WHERE id = $1::INTEGER AND status = 'draft'", &[&id, &delay]).await {
+      Ok(cnt) => cnt > 0,
+      Err(e) => {
+         log::error!("confirm({}): {}", id, e);
+         false
+      }
+   }
+}
+
+/// Отменяет черновик или ещё не доставленное сообщение, возвращает успешность
+pub async fn cancel(id: i32) -> bool {
+   let client = DB.get().unwrap();
+   match client.execute("UPDATE submissions SET status = 'cancelled', queued = FALSE WHERE id = $1::INTEGER AND (status = 'draft' OR status = 'pending' AND queued)", &[&id]).await {
+      Ok(cnt) => cnt > 0,
+      Err(e) => {
+         log::error!("cancel({}): {}", id, e);
+         false
+      }
+   }
+}
+
+/// Забывает авторов сообщений, по которым всё решено: отклонённых, отозванных, отменённых, удалённых
+/// и опубликованных, которые уже нельзя отозвать. Опубликованное бот может удалить из чата
+/// только в течение двух суток, столько код автора и хранится
+pub async fn forget_authors() {
+   let client = DB.get().unwrap();
+   if let Err(e) = client.execute("UPDATE submissions SET author_id = NULL WHERE author_id IS NOT NULL
+      AND (status IN ('rejected', 'expired', 'retracted', 'cancelled', 'deleted') OR status = 'approved' AND COALESCE(decided_at, created) < NOW() - INTERVAL '2 days')", &[]).await {
+      log::error!("forget_authors(): {}", e);
+   }
+}
+
+/// Удаляет черновики и части альбомов, которые автор так и не отправил за сутки
+pub async fn drop_drafts() {
+   let client = DB.get().unwrap();
+   if let Err(e) = client.execute("DELETE FROM submissions WHERE status = 'draft' AND created < NOW() - INTERVAL '1 day'", &[]).await {
+      log::error!("drop_drafts(): {}", e);
+   }
+   if let Err(e) = client.execute("DELETE FROM album_parts WHERE created < NOW() - INTERVAL '1 day'", &[]).await {
+      log::error!("drop_drafts 2 (): {}", e);
+   }
+}
+
+/// Сохраняет часть альбома, подпись с разметкой в виде JSON
+pub async fn add_album_part(media_group_id: &str, message_id: i32, media: &str, caption: &str, entities: &str) {
+   let client = DB.get().unwrap();
+   if let Err(e) = client.execute("INSERT INTO album_parts (media_group_id, message_id, media, caption, entities, created) VALUES ($1::VARCHAR(50), $2::INTEGER, $3::TEXT, $4::TEXT, $5::TEXT, NOW()) ON CONFLICT DO NOTHING", &[&media_group_id, &message_id, &media, &caption, &entities]).await {
+      log::error!("add_album_part({}, {}): {}", media_group_id, message_id, e);
+   }
+}
+
+/// Возвращает вложения альбома по порядку и первую непустую подпись с разметкой в виде JSON
+pub async fn album(media_group_id: &str) -> (Vec<Media>, String, String) {
+   let client = DB.get().unwrap();
+   match client.query("SELECT media, caption, entities FROM album_parts WHERE media_group_id = $1::VARCHAR(50) ORDER BY message_id", &[&media_group_id]).await {
+      Ok(rows) => {
+         let media = rows.iter().filter_map(|row| Media::parse(row.get(0))).collect();
+         let (caption, entities) = rows.iter()
+         .map(|row| (row.get::<_, String>(1), row.get::<_, String>(2)))
+         .find(|(caption, _)| !caption.is_empty())
+         .unwrap_or_default();
+         (media, caption, entities)
+      }
+      Err(e) => {
+         log::error!("album({}): {}", media_group_id, e);
+         (Vec::new(), String::new(), String::new())
+      }
+   }
+}
+
+/// Возвращает чаты с сообщениями, ждущими набора пакета: название, число сообщений и
+/// сколько секунд ждёт самое старое
+pub async fn waiting_batches() -> Vec<(String, i64, i64)> {
+   let client = DB.get().unwrap();
+   let res = client.query("SELECT chat_name, COUNT(*), EXTRACT(EPOCH FROM NOW() - MIN(created))::BIGINT FROM submissions WHERE queued AND deliver_at IS NULL GROUP BY chat_name", &[]).await;
+   match res {
+      Ok(rows) => rows.into_iter().map(|row| (row.get(0), row.get(1), row.get(2))).collect(),
+      Err(e) => {
+         log::error!("waiting_batches(): {}", e);
+         Vec::new()
+      }
+   }
+}
+
+/// Назначает доставку всех ждущих пакета сообщений чата на текущий момент
+pub async fn release_batch(chat_name: &str) {
+   let client = DB.get().unwrap();
+   if let Err(e) = client.execute("UPDATE submissions SET deliver_at = NOW() WHERE chat_name = $1::VARCHAR(100) AND queued AND deliver_at IS NULL", &[&chat_name]).await {
+      log::error!("release_batch({}): {}", chat_name, e);
+   }
+}
+
+/// Забирает из очереди сообщения, которые пора доставить
+pub async fn take_due() -> Vec<Submission> {
+   let client = DB.get().unwrap();
+   let res = client.query(format!("UPDATE submissions SET queued = FALSE WHERE queued AND deliver_at <= NOW() RETURNING {}", SUBMISSION_FIELDS).as_str(), &[]).await;
+   match res {
+      Ok(rows) => rows.into_iter().map(Submission::from).collect(),
+      Err(e) => {
+         log::error!("take_due(): {}", e);
+         Vec::new()
+      }
+   }
+}
+
+/// Возвращает сообщение по его номеру
+pub async fn submission(id: i32) -> Option<Submission> {
+   let client = DB.get().unwrap();
+   let res = client.query_one(format!("SELECT {} FROM submissions WHERE id = $1::INTEGER", SUBMISSION_FIELDS).as_str(), &[&id]).await;
+   match res {
+      Ok(data) => Some(Submission::from(data)),
+      _ => None,
+   }
+}
+
+/// Возвращает последние сообщения автора, которые ещё можно отозвать
+pub async fn author_submissions(author_id: i64) -> Vec<Submission> {
+   let client = DB.get().unwrap();
+   let res = client.query(format!("SELECT {} FROM submissions WHERE author_id = $1::BIGINT AND (status = 'pending' OR status = 'approved' AND published_id IS NOT NULL) ORDER BY id DESC LIMIT 10", SUBMISSION_FIELDS).as_str(), &[&author_id]).await;
+   match res {
+      Ok(rows) => rows.into_iter().map(Submission::from).collect(),
+      Err(e) => {
+         log::error!("author_submissions({}): {}", author_id, e);
+         Vec::new()
+      }
+   }
+}
+
+/// Запоминает код сообщения у администратора
+pub async fn set_card(id: i32, chat_id: i64, message_id: i32) {
+   let client = DB.get().unwrap();
+   if let Err(e) = client.execute("UPDATE submissions SET card_chat = $2::BIGINT, card_id = $3::INTEGER, card_at = NOW() WHERE id = $1::INTEGER", &[&id, &chat_id, &message_id]).await {
+      log::error!("set_card({}, {}): {}", id, message_id, e);
+   }
+}
+
+/// Фиксирует решение по сообщению, ожидающему модерации
+/// Возвращает false, если решение уже было принято ранее
+pub async fn decide(id: i32, status: &str) -> bool {
+   let client = DB.get().unwrap();
+   match client.execute("UPDATE submissions SET status = $2::VARCHAR(20), decided_at = NOW() WHERE id = $1::INTEGER AND status = 'pending'", &[&id, &status]).await {
+      Ok(cnt) => cnt > 0,
+      Err(e) => {
+         log::error!("decide({}, {}): {}", id, status, e);
+         false
+      }
+   }
+}
+
+/// Учитывает голос модератора и возвращает число голосов за и против
+pub async fn vote(id: i32, voter_id: i64, approve: bool) -> (i64, i64) {
+   let client = DB.get().unwrap();
+   if let Err(e) = client.execute("INSERT INTO votes (submission_id, voter_id, approve) VALUES ($1::INTEGER, $2::BIGINT, $3::BOOLEAN) ON CONFLICT (submission_id, voter_id) DO UPDATE SET approve = EXCLUDED.approve", &[&id, &voter_id, &approve]).await {
+      log::error!("vote({}, {}): {}", id, voter_id, e);
+   }
+   votes(id).await
+}
+
+/// Возвращает число голосов модераторов за и против
+pub async fn votes(id: i32) -> (i64, i64) {
+   let client = DB.get().unwrap();
+   match client.query_one("SELECT COUNT(*) FILTER (WHERE approve), COUNT(*) FILTER (WHERE NOT approve) FROM votes WHERE submission_id = $1::INTEGER", &[&id]).await {
+      Ok(row) => (row.get(0), row.get(1)),
+      Err(e) => {
+         log::error!("votes({}): {}", id, e);
+         (0, 0)
+      }
+   }
+}
+
+/// Сообщение, ожидающее решения модераторов, со всем нужным для проверки сроков
+pub struct Awaiting {
+   pub submission: Submission,
+   // Сколько секунд ждёт решения
+   pub age: i64,
+   pub admin_id: i64,
+   // Из настроек чата заполнены только кворум и сроки
+   pub settings: Settings,
+   // Голоса за и против
+   pub votes: (i64, i64),
+}
+
+/// Возвращает одним запросом сообщения, ожидающие решения модераторов в чатах со сроками рассмотрения
+pub async fn awaiting_review() -> Vec<Awaiting> {
+   let client = DB.get().unwrap();
+   let query = format!("SELECT {}, EXTRACT(EPOCH FROM NOW() - card_at)::BIGINT, c.user_id, q.value, l.value,
+      (SELECT COUNT(*) FROM votes v WHERE v.submission_id = s.id AND v.approve),
+      (SELECT COUNT(*) FROM votes v WHERE v.submission_id = s.id AND NOT v.approve)
+      FROM (SELECT * FROM submissions WHERE status = 'pending' AND card_id IS NOT NULL AND card_at IS NOT NULL) s
+      INNER JOIN chats c ON c.chat_name = s.chat_name
+      INNER JOIN settings l ON l.chat_name = s.chat_name AND l.key = 'sla' AND l.value <> 'off'
+      LEFT JOIN settings q ON q.chat_name = s.chat_name AND q.key = 'quorum'",
+      SUBMISSION_FIELDS.split(", ").map(|field| format!("s.{}", field)).collect::<Vec<_>>().join(", "));
+   match client.query(query.as_str(), &[]).await {
+      Ok(rows) => rows.into_iter()
+      .map(|row| {
+         let fields = row.len() - 6;
+         let pairs = [("quorum", row.get::<_, Option<String>>(fields + 2)), ("sla", row.get(fields + 3))];
+         Awaiting {
+            age: row.get(fields),
+            admin_id: row.get(fields + 1),
+            settings: Settings::from_pairs(pairs.iter().filter_map(|(key, value)| value.clone().map(|value| (String::from(*key), value)))),
+            votes: (row.get(fields + 4), row.get(fields + 5)),
+            submission: Submission::from(row),
+         }
+      })
+      .collect(),
+      Err(e) => {
+         log::error!("awaiting_review(): {}", e);
+         Vec::new()
+      }
+   }
+}
+
+/// Отмечает, что модераторам напомнили о сообщении, возвращает false, если уже напоминали
+pub async fn mark_reminded(id: i32) -> bool {
+   let client = DB.get().unwrap();
+   match client.execute("UPDATE submissions SET reminded = TRUE WHERE id = $1::INTEGER AND NOT reminded", &[&id]).await {
+      Ok(cnt) => cnt > 0,
+      Err(e) => {
+         log::error!("mark_reminded({}): {}", id, e);
+         false
+      }
+   }
+}
+
+/// Отмечает, что сообщение передано запасным модераторам, возвращает false, если уже передано
+pub async fn mark_escalated(id: i32) -> bool {
+   let client = DB.get().unwrap();
+   match client.execute("UPDATE submissions SET escalated = TRUE, reminded = TRUE WHERE id = $1::INTEGER AND NOT escalated", &[&id]).await {
+      Ok(cnt) => cnt > 0,
+      Err(e) => {
+         log::error!("mark_escalated({}): {}", id, e);
+         false
+      }
+   }
+}
+
+/// Запоминает коды опубликованного в чате сообщения, для альбома их несколько
+pub async fn published(id: i32, message_ids: &[i32]) {
+   let client = DB.get().unwrap();
+   let (first, more) = match message_ids.split_first() {
+      Some((first, more)) => (first, more.to_vec()),
+      None => return,
+   };
+   if let Err(e) = client.execute("UPDATE submissions SET published_id = $2::INTEGER, published_more = $3::INTEGER[] WHERE id = $1::INTEGER", &[&id, first, &more]).await {
+      log::error!("published({}, {}): {}", id, first, e);
+   }
+}
+
+/// Помечает опубликованное сообщение удалённым и возвращает его коды в чате
+pub async fn unpublish(id: i32) -> Option<Vec<i32>> {
+   let client = DB.get().unwrap();
+   let res = client.query_opt("UPDATE submissions SET status = 'deleted' WHERE id = $1::INTEGER AND status = 'approved' AND published_id IS NOT NULL RETURNING published_id, published_more", &[&id]).await;
+   match res {
+      Ok(data) => data.map(|row| {
+         let more: Option<Vec<i32>> = row.get(1);
+         std::iter::once(row.get(0)).chain(more.unwrap_or_default()).collect()
+      }),
+      Err(e) => {
+         log::error!("unpublish({}): {}", id, e);
+         None
+      }
+   }
+}
+
+/// Возвращает солёный хеш автора для указанного чата. По хешу нельзя узнать автора,
+/// но можно опознать его повторно, причём в разных чатах хеши у него разные
+pub fn author_hash(chat_name: &str, user_id: i64) -> String {
+   // Соль не зависит от токена бота, чтобы его замена не снимала блокировки.
+   // Её наличие проверяется при запуске
+   let salt = env::var("HASH_SALT").unwrap_or_default();
+
+   let mut hasher = Sha256::new();
+   hasher.update(salt);
+   hasher.update(b"\n");
+   hasher.update(chat_name);
+   hasher.update(b"\n");
+   hasher.update(user_id.to_le_bytes());
+   hex::encode(hasher.finalize())
+}
+
+/// Блокирует автора в чате на указанное число дней, 0 - навсегда. Возвращает номер блокировки
+pub async fn ban(chat_name: &str, author_hash: &str, days: i32) -> Option<i32> {
+   let client = DB.get().unwrap();
+   let res = client.query_one("INSERT INTO bans (chat_name, author_hash, created, until) VALUES ($1::VARCHAR(100), $2::VARCHAR(64), NOW(), CASE WHEN $3::INTEGER > 0 THEN NOW() + $3::INTEGER * INTERVAL '1 day' END) RETURNING id", &[&chat_name, &author_hash, &days]).await;
+   match res {
+      Ok(data) => Some(data.get(0)),
+      Err(e) => {
+         log::error!("ban({}, {}): {}", chat_name, days, e);
+         None
+      }
+   }
+}
+
+/// Если автор заблокирован в чате, возвращает срок окончания блокировки (None - бессрочно)
+pub async fn ban_until(chat_name: &str, author_hash: &str) -> Option<Option<String>> {
+   let client = DB.get().unwrap();
+   let res = client.query_opt("SELECT to_char(until, 'DD.MM.YYYY HH24:MI') FROM bans WHERE chat_name = $1::VARCHAR(100) AND author_hash = $2::VARCHAR(64) AND (until IS NULL OR until > NOW()) ORDER BY until DESC NULLS FIRST LIMIT 1", &[&chat_name, &author_hash]).await;
+   match res {
+      Ok(data) => data.map(|row| row.get(0)),
+      Err(e) => {
+         log::error!("ban_until({}): {}", chat_name, e);
+         None
+      }
+   }
+}
+
+/// Возвращает действующие блокировки чата: номер, дату начала и окончания (None - бессрочно)
+pub async fn bans(chat_name: &str) -> Vec<(i32, String, Option<String>)> {
+   let client = DB.get().unwrap();
+   let res = client.query("SELECT id, to_char(created, 'DD.MM.YYYY HH24:MI'), to_char(until, 'DD.MM.YYYY HH24:MI') FROM bans WHERE chat_name = $1::VARCHAR(100) AND (until IS NULL OR until > NOW()) ORDER BY id", &[&chat_name]).await;
+   match res {
+      Ok(rows) => rows.into_iter().map(|row| (row.get(0), row.get(1), row.get(2))).collect(),
+      Err(e) => {
+         log::error!("bans({}): {}", chat_name, e);
+         Vec::new()
+      }
+   }
+}
+
+/// Снимает блокировку с указанным номером, возвращает false, если такой не нашлось
+pub async fn unban(chat_name: &str, id: i32) -> bool {
+   let client = DB.get().unwrap();
+   match client.execute("DELETE FROM bans WHERE id = $1::INTEGER AND chat_name = $2::VARCHAR(100)", &[&id, &chat_name]).await {
+      Ok(cnt) => cnt > 0,
+      Err(e) => {
+         log::error!("unban({}, {}): {}", chat_name, id, e);
+         false
+      }
+   }
+}
+
+/// Добавляет правило фильтрации для чата и возвращает его номер
+pub async fn add_filter(chat_name: &str, action: filters::Action, kind: filters::Kind, pattern: &str) -> Option<i32> {
+   let client = DB.get().unwrap();
+   let res = client.query_one("INSERT INTO filters (chat_name, action, kind, pattern) VALUES ($1::VARCHAR(100), $2::VARCHAR(10), $3::VARCHAR(10), $4::TEXT) RETURNING id", &[&chat_name, &action.as_str(), &kind.as_str(), &pattern]).await;
+   match res {
+      Ok(data) => Some(data.get(0)),
+      Err(e) => {
+         log::error!("add_filter({}, {}): {}", chat_name, pattern, e);
+         None
+      }
+   }
+}
+
+/// Возвращает правила фильтрации чата
+pub async fn filters(chat_name: &str) -> Vec<Rule> {
+   let client = DB.get().unwrap();
+   let res = client.query("SELECT id, action, kind, pattern FROM filters WHERE chat_name = $1::VARCHAR(100) ORDER BY id", &[&chat_name]).await;
+   match res {
+      Ok(rows) => rows.into_iter()
+      .filter_map(|row| {
+         let action: String = row.get(1);
+         let kind: String = row.get(2);
+         Some(Rule {
+            id: row.get(0),
+            action: action.parse().ok()?,
+            kind: kind.parse().ok()?,
+            pattern: row.get(3),
+         })
+      })
+      .collect(),
+      Err(e) => {
+         log::error!("filters({}): {}", chat_name, e);
+         Vec::new()
+      }
+   }
+}
+
+/// Удаляет правило фильтрации, возвращает false, если такого не нашлось
+pub async fn delete_filter(chat_name: &str, id: i32) -> bool {
+   let client = DB.get().unwrap();
+   match client.execute("DELETE FROM filters WHERE id = $1::INTEGER AND chat_name = $2::VARCHAR(100)", &[&id, &chat_name]).await {
+      Ok(cnt) => cnt > 0,
+      Err(e) => {
+         log::error!("delete_filter({}, {}): {}", chat_name, id, e);
+         false
+      }
+   }
+}
+
+/// Возвращает настройки чата
+pub async fn settings(chat_name: &str) -> Settings {
+   let client = DB.get().unwrap();
+   let res = client.query("SELECT key, value FROM settings WHERE chat_name = $1::VARCHAR(100)", &[&chat_name]).await;
+   match res {
+      Ok(rows) => Settings::from_pairs(rows.into_iter().map(|row| (row.get(0), row.get(1)))),
+      Err(e) => {
+         log::error!("settings({}): {}", chat_name, e);
+         Settings::default()
+      }
+   }
+}
+
+/// Сохраняет значение настройки чата
+pub async fn set_setting(chat_name: &str, key: &str, value: &str) -> bool {
+   let client = DB.get().unwrap();
+   match client.execute("INSERT INTO settings (chat_name, key, value) VALUES ($1::VARCHAR(100), $2::VARCHAR(20), $3::TEXT) ON CONFLICT (chat_name, key) DO UPDATE SET value = EXCLUDED.value", &[&chat_name, &key, &value]).await {
+      Ok(_) => true,
+      Err(e) => {
+         log::error!("set_setting({}, {}, {}): {}", chat_name, key, value, e);
+         false
+      }
+   }
+}
+
+// Неугадываемый код для ссылок
+fn new_token() -> String {
+   rand::thread_rng()
+   .sample_iter(&Alphanumeric)
+   .take(16)
+   .map(char::from)
+   .collect()
+}
+
+/// Возвращает код постоянной ссылки на чат, создавая новый, если его нет или требуется замена
+pub async fn chat_link(chat_name: &str, renew: bool) -> Option<String> {
+   let client = DB.get().unwrap();
+
+   if !renew {
+      match client.query_one("SELECT link FROM chats WHERE chat_name = $1::VARCHAR(100)", &[&chat_name]).await {
+         Ok(row) => if let Some(link) = row.get::<_, Option<String>>(0) { return Some(link) },
+         Err(e) => {
+            log::error!("chat_link({}): {}", chat_name, e);
+            return None;
+         }
+      }
+   }
+
+   // Прежняя ссылка при замене перестаёт действовать
+   let link = new_token();
+   match client.execute("UPDATE chats SET link = $1::VARCHAR(32) WHERE chat_name = $2::VARCHAR(100)", &[&link, &chat_name]).await {
+      Ok(count) if count > 0 => Some(link),
+      Ok(_) => None,
+      Err(e) => {
+         log::error!("chat_link 2 ({}): {}", chat_name, e);
+         None
+      }
+   }
+}
+
+/// Выбирает для пользователя чат по коду постоянной ссылки, возвращает название чата
+/// и выбран ли он: чат только по приглашениям выбирается лишь приглашённым и администратору
+pub async fn follow_link(user_id: i64, link: &str) -> Option<(String, bool)> {
+   let client = DB.get().unwrap();
+   let query = "SELECT chat_name, user_id = $2::BIGINT
+      OR NOT EXISTS (SELECT 1 FROM settings WHERE settings.chat_name = chats.chat_name AND key = 'visibility' AND value = 'invite')
+      OR EXISTS (SELECT 1 FROM access JOIN invites ON invites.id = access.invite_id WHERE invites.chat_name = chats.chat_name AND access.user_id = $2::BIGINT)
+      FROM chats WHERE link = $1::VARCHAR(32)";
+   let (chat_name, allowed): (String, bool) = match client.query_opt(query, &[&link, &user_id]).await {
+      Ok(row) => row.map(|row| (row.get(0), row.get(1)))?,
+      Err(e) => {
+         log::error!("follow_link({}, {}): {}", user_id, link, e);
+         return None;
+      }
+   };
+
+   if allowed {
+      set_target(user_id, &chat_name).await;
+   }
+   Some((chat_name, allowed))
+}
+
+/// Создаёт приглашение в чат, возвращает его номер и код для ссылки
+pub async fn new_invite(chat_name: &str) -> Option<(i32, String)> {
+   let client = DB.get().unwrap();
+   let token = new_token();
+
+   match client.query_one("INSERT INTO invites (chat_name, token, created) VALUES ($1::VARCHAR(100), $2::VARCHAR(32), NOW()) RETURNING id", &[&chat_name, &token]).await {
+      Ok(row) => Some((row.get(0), token)),
+      Err(e) => {
+         log::error!("new_invite({}): {}", chat_name, e);
+         None
+      }
+   }
+}
+
+/// Возвращает приглашения в чат: номер, код и дату создания
+pub async fn invites(chat_name: &str) -> Vec<(i32, String, String)> {
+   let client = DB.get().unwrap();
+   match client.query("SELECT id, token, to_char(created, 'DD.MM.YYYY') FROM invites WHERE chat_name = $1::VARCHAR(100) ORDER BY id", &[&chat_name]).await {
+      Ok(rows) => rows.into_iter().map(|row| (row.get(0), row.get(1), row.get(2))).collect(),
+      Err(e) => {
+         log::error!("invites({}): {}", chat_name, e);
+         Vec::new()
+      }
+   }
+}
+
+/// Отзывает приглашение вместе с доступом принявших его, возвращает успешность
+pub async fn revoke_invite(chat_name: &str, id: i32) -> bool {
+   let client = DB.get().unwrap();
+   match client.execute("DELETE FROM invites WHERE id = $1::INTEGER AND chat_name = $2::VARCHAR(100)", &[&id, &chat_name]).await {
+      Ok(count) => count > 0,
+      Err(e) => {
+         log::error!("revoke_invite({}, {}): {}", chat_name, id, e);
+         false
+      }
+   }
+}
+
+/// Принимает приглашение по коду: даёт доступ к чату и выбирает его для отправки,
+/// возвращает название чата
+pub async fn accept_invite(user_id: i64, token: &str) -> Option<String> {
+   let client = DB.get().unwrap();
+   let row = match client.query_opt("SELECT id, chat_name FROM invites WHERE token = $1::VARCHAR(32)", &[&token]).await {
+      Ok(row) => row?,
+      Err(e) => {
+         log::error!("accept_invite({}, {}): {}", user_id, token, e);
+         return None;
+      }
+   };
+   let invite_id: i32 = row.get(0);
+   let chat_name: String = row.get(1);
+
+   if let Err(e) = client.execute("INSERT INTO access (user_id, invite_id) VALUES ($1::BIGINT, $2::INTEGER) ON CONFLICT DO NOTHING", &[&user_id, &invite_id]).await {
+      log::error!("accept_invite 2 ({}, {}): {}", user_id, invite_id, e);
+      return None;
+   }
+
+   set_target(user_id, &chat_name).await;
+   Some(chat_name)
+}
+
+/// Проверяет, есть ли у пользователя действующее приглашение в чат
+pub async fn has_access(chat_name: &str, user_id: i64) -> bool {
+   let client = DB.get().unwrap();
+   match client.query_opt("SELECT 1 FROM access JOIN invites ON invites.id = access.invite_id WHERE invites.chat_name = $1::VARCHAR(100) AND access.user_id = $2::BIGINT LIMIT 1", &[&chat_name, &user_id]).await {
+      Ok(row) => row.is_some(),
+      Err(e) => {
+         log::error!("has_access({}, {}): {}", chat_name, user_id, e);
+         false
+      }
+   }
+}
+
+/// Возвращает чат, заранее выбранный пользователем для отправки
+pub async fn target(user_id: i64) -> Option<String> {
+   let client = DB.get().unwrap();
+   match client.query_opt("SELECT target FROM users WHERE user_id = $1::BIGINT", &[&user_id]).await {
+      Ok(row) => row.and_then(|row| row.get(0)),
+      Err(e) => {
+         log::error!("target({}): {}", user_id, e);
+         None
+      }
+   }
+}
+
+/// Запоминает чат, выбранный пользователем для отправки
+pub async fn set_target(user_id: i64, chat_name: &str) {
+   let client = DB.get().unwrap();
+   if let Err(e) = client.execute("INSERT INTO users (user_id, target) VALUES ($1::BIGINT, $2::VARCHAR(100)) ON CONFLICT (user_id) DO UPDATE SET target = EXCLUDED.target", &[&user_id, &chat_name]).await {
+      log::error!("set_target({}, {}): {}", user_id, chat_name, e);
+   }
+}
+
+/// Забывает заранее выбранный пользователем чат
+pub async fn clear_target(user_id: i64) {
+   let client = DB.get().unwrap();
+   if let Err(e) = client.execute("UPDATE users SET target = NULL WHERE user_id = $1::BIGINT", &[&user_id]).await {
+      log::error!("clear_target({}): {}", user_id, e);
+   }
+}
+
+/// Возвращает последний выбранный пользователем чат
+pub async fn last_chat(user_id: i64) -> Option<String> {
+   let client = DB.get().unwrap();
+   match client.query_opt("SELECT last_chat FROM users WHERE user_id = $1::BIGINT", &[&user_id]).await {
+      Ok(row) => row.and_then(|row| row.get(0)),
+      Err(e) => {
+         log::error!("last_chat({}): {}", user_id, e);
+         None
+      }
+   }
+}
+
+/// Запоминает последний выбранный пользователем чат
+pub async fn set_last_chat(user_id: i64, chat_name: &str) {
+   let client = DB.get().unwrap();
+   if let Err(e) = client.execute("INSERT INTO users (user_id, last_chat) VALUES ($1::BIGINT, $2::VARCHAR(100)) ON CONFLICT (user_id) DO UPDATE SET last_chat = EXCLUDED.last_chat", &[&user_id, &chat_name]).await {
+      log::error!("set_last_chat({}, {}): {}", user_id, chat_name, e);
+   }
+}
+
+/// Добавляет тему форума для отправки или меняет её название
+pub async fn add_topic(chat_name: &str, thread_id: i32, title: &str) -> bool {
+   let client = DB.get().unwrap();
+   match client.execute("INSERT INTO topics (chat_name, thread_id, title) VALUES ($1::VARCHAR(100), $2::INTEGER, $3::VARCHAR(100)) ON CONFLICT (chat_name, thread_id) DO UPDATE SET title = EXCLUDED.title", &[&chat_name, &thread_id, &title]).await {
+      Ok(_) => true,
+      Err(e) => {
+         log::error!("add_topic({}, {}): {}", chat_name, thread_id, e);
+         false
+      }
+   }
+}
+
+/// Возвращает темы форума, в которые можно отправлять: номер и название
+pub async fn topics(chat_name: &str) -> Vec<(i32, String)> {
+   let client = DB.get().unwrap();
+   match client.query("SELECT thread_id, title FROM topics WHERE chat_name = $1::VARCHAR(100) ORDER BY title", &[&chat_name]).await {
+      Ok(rows) => rows.into_iter().map(|row| (row.get(0), row.get(1))).collect(),
+      Err(e) => {
+         log::error!("topics({}): {}", chat_name, e);
+         Vec::new()
+      }
+   }
+}
+
+/// Удаляет тему форума из разрешённых, возвращает false, если такой не нашлось
+pub async fn delete_topic(chat_name: &str, thread_id: i32) -> bool {
+   let client = DB.get().unwrap();
+   match client.execute("DELETE FROM topics WHERE chat_name = $1::VARCHAR(100) AND thread_id = $2::INTEGER", &[&chat_name, &thread_id]).await {
+      Ok(cnt) => cnt > 0,
+      Err(e) => {
+         log::error!("delete_topic({}, {}): {}", chat_name, thread_id, e);
+         false
+      }
+   }
+}
+
+/// Запоминает выбранную автором тему черновика вместе с обновлёнными пометками, возвращает успешность
+pub async fn set_thread(id: i32, thread_id: i32, notes: &str) -> bool {
+   let client = DB.get().unwrap();
+   match client.execute("UPDATE submissions SET thread_id = $2::INTEGER, notes = $3::TEXT WHERE id = $1::INTEGER AND status = 'draft'", &[&id, &thread_id, &notes]).await {
+      Ok(cnt) => cnt > 0,
+      Err(e) => {
+         log::error!("set_thread({}, {}): {}", id, thread_id, e);
+         false
+      }
+   }
+}
+
+/// Запоминает выбор автора публиковать черновик без звука или со звуком
+pub async fn set_silent(id: i32, silent: bool) -> bool {
+   let client = DB.get().unwrap();
+   match client.execute("UPDATE submissions SET silent = $2::BOOLEAN WHERE id = $1::INTEGER AND status = 'draft'", &[&id, &silent]).await {
+      Ok(cnt) => cnt > 0,
+      Err(e) => {
+         log::error!("set_silent({}, {}): {}", id, silent, e);
+         false
+      }
+   }
+}
+
+/// Возвращает код ссылки для подключения запасных модераторов, при замене прежние модераторы забываются
+pub async fn backup_link(chat_name: &str, renew: bool) -> Option<String> {
+   let client = DB.get().unwrap();
+
+   if !renew {
+      match client.query_one("SELECT backup_link FROM chats WHERE chat_name = $1::VARCHAR(100)", &[&chat_name]).await {
+         Ok(row) => if let Some(link) = row.get::<_, Option<String>>(0) { return Some(link) },
+         Err(e) => {
+            log::error!("backup_link({}): {}", chat_name, e);
+            return None;
+         }
+      }
+   } else if let Err(e) = client.execute("DELETE FROM backups WHERE chat_name = $1::VARCHAR(100)", &[&chat_name]).await {
+      log::error!("backup_link 2 ({}): {}", chat_name, e);
+      return None;
+   }
+
+   let link = new_token();
+   match client.execute("UPDATE chats SET backup_link = $1::VARCHAR(32) WHERE chat_name = $2::VARCHAR(100)", &[&link, &chat_name]).await {
+      Ok(count) if count > 0 => Some(link),
+      Ok(_) => None,
+      Err(e) => {
+         log::error!("backup_link 3 ({}): {}", chat_name, e);
+         None
+      }
+   }
+}
+
+/// Подключает пользователя запасным модератором по коду ссылки, возвращает название чата
+pub async fn join_backups(user_id: i64, name: &str, link: &str) -> Option<String> {
+   let client = DB.get().unwrap();
+   let chat_name: String = match client.query_opt("SELECT chat_name FROM chats WHERE backup_link = $1::VARCHAR(32)", &[&link]).await {
+      Ok(row) => row?.get(0),
+      Err(e) => {
+         log::error!("join_backups({}, {}): {}", user_id, link, e);
+         return None;
+      }
+   };
+
+   let name: String = name.chars().take(100).collect();
+   if let Err(e) = client.execute("INSERT INTO backups (chat_name, user_id, name) VALUES ($1::VARCHAR(100), $2::BIGINT, $3::VARCHAR(100)) ON CONFLICT (chat_name, user_id) DO UPDATE SET name = EXCLUDED.name", &[&chat_name, &user_id, &name]).await {
+      log::error!("join_backups 2 ({}, {}): {}", user_id, chat_name, e);
+      return None;
+   }
+   Some(chat_name)
+}
+
+/// Возвращает запасных модераторов чата: код пользователя и имя
+pub async fn backups(chat_name: &str) -> Vec<(i64, String)> {
+   let client = DB.get().unwrap();
+   match client.query("SELECT user_id, name FROM backups WHERE chat_name = $1::VARCHAR(100) ORDER BY name", &[&chat_name]).await {
+      Ok(rows) => rows.into_iter().map(|row| (row.get(0), row.get(1))).collect(),
+      Err(e) => {
+         log::error!("backups({}): {}", chat_name, e);
+         Vec::new()
+      }
+   }
+}
+
+/// Возвращает чаты, которые модерирует пользователь: свой чат и те, где он запасной модератор,
+/// а для группы модераторов - чаты, модерируемые в ней
+pub async fn moderated_chats(user_id: i64, chat_id: i64) -> Vec<String> {
+   let client = DB.get().unwrap();
+   let res = if chat_id < 0 {
+      client.query("SELECT chat_name FROM chats WHERE modchat = $1::BIGINT ORDER BY chat_name", &[&chat_id]).await
+   } else {
+      client.query("SELECT chat_name FROM chats WHERE user_id = $1::BIGINT UNION SELECT chat_name FROM backups WHERE user_id = $1::BIGINT ORDER BY chat_name", &[&user_id]).await
+   };
+   match res {
+      Ok(rows) => rows.into_iter().map(|row| row.get(0)).collect(),
+      Err(e) => {
+         log::error!("moderated_chats({}, {}): {}", user_id, chat_id, e);
+         Vec::new()
+      }
+   }
+}
+
+/// Возвращает число сообщений, ждущих решения модераторов, по чатам
+pub async fn queue_counts(chats: &[String]) -> Vec<(String, i64)> {
+   let client = DB.get().unwrap();
+   match client.query("SELECT chat_name, COUNT(*) FROM submissions WHERE status = 'pending' AND card_id IS NOT NULL AND chat_name = ANY($1::VARCHAR[]) GROUP BY chat_name ORDER BY chat_name", &[&chats]).await {
+      Ok(rows) => rows.into_iter().map(|row| (row.get(0), row.get(1))).collect(),
+      Err(e) => {
+         log::error!("queue_counts(): {}", e);
+         Vec::new()
+      }
+   }
+}
+
+/// Возвращает ждущие решения сообщения указанных чатов по порядку, начиная со старых,
+/// и сколько секунд каждое ждёт
+pub async fn queue(chats: &[String], offset: i64, limit: i64) -> Vec<(Submission, i64)> {
+   let client = DB.get().unwrap();
+   let query = format!("SELECT {}, EXTRACT(EPOCH FROM NOW() - COALESCE(card_at, created))::BIGINT FROM submissions WHERE status = 'pending' AND card_id IS NOT NULL AND chat_name = ANY($1::VARCHAR[]) ORDER BY id OFFSET $2::BIGINT LIMIT $3::BIGINT", SUBMISSION_FIELDS);
+   match client.query(query.as_str(), &[&chats, &offset, &limit]).await {
+      Ok(rows) => rows.into_iter()
+      .map(|row| {
+         let age = row.get(row.len() - 1);
+         (Submission::from(row), age)
+      })
+      .collect(),
+      Err(e) => {
+         log::error!("queue({}): {}", offset, e);
+         Vec::new()
+      }
+   }
+}
+
+/// Возвращает число сообщений чата по состояниям за последние дни и за всё время
+pub async fn stats(chat_name: &str, days: i32) -> Vec<(String, i64, i64)> {
+   let client = DB.get().unwrap();
+   match client.query("SELECT status, COUNT(*) FILTER (WHERE created > NOW() - $2::INTEGER * INTERVAL '1 day'), COUNT(*) FROM submissions WHERE chat_name = $1::VARCHAR(100) AND status <> 'draft' GROUP BY status ORDER BY status", &[&chat_name, &days]).await {
+      Ok(rows) => rows.into_iter().map(|row| (row.get(0), row.get(1), row.get(2))).collect(),
+      Err(e) => {
+         log::error!("stats({}): {}", chat_name, e);
+         Vec::new()
+      }
+   }
+}
+
+// Хеш токена API для хранения и поиска
+fn api_token_hash(token: &str) -> String {
+   hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Выдаёт новый токен API для чата пользователя взамен прежнего, возвращает название чата и токен
+pub async fn issue_api_token(user_id: i64) -> Option<(String, String)> {
+   let client = DB.get().unwrap();
+   let token = format!("{}{}", new_token(), new_token());
+   match client.query_opt("UPDATE chats SET api_token = $2::VARCHAR(64) WHERE user_id = $1::BIGINT RETURNING chat_name", &[&user_id, &api_token_hash(&token)]).await {
+      Ok(row) => Some((row?.get(0), token)),
+      Err(e) => {
+         log::error!("issue_api_token({}): {}", user_id, e);
+         None
+      }
+   }
+}
+
+/// Отзывает токен API чата пользователя, возвращает успешность
+pub async fn revoke_api_token(user_id: i64) -> bool {
+   let client = DB.get().unwrap();
+   match client.execute("UPDATE chats SET api_token = NULL WHERE user_id = $1::BIGINT AND api_token IS NOT NULL", &[&user_id]).await {
+      Ok(cnt) => cnt > 0,
+      Err(e) => {
+         log::error!("revoke_api_token({}): {}", user_id, e);
+         false
+      }
+   }
+}
+
+/// Возвращает чат и его администратора по токену API
+pub async fn api_chat(token: &str) -> Option<(String, i64)> {
+   let client = DB.get().unwrap();
+   match client.query_opt("SELECT chat_name, user_id FROM chats WHERE api_token = $1::VARCHAR(64)", &[&api_token_hash(token)]).await {
+      Ok(row) => row.map(|row| (row.get(0), row.get(1))),
+      Err(e) => {
+         log::error!("api_chat(): {}", e);
+         None
+      }
+   }
+}
+
+/// Возвращает сообщения чата, кроме черновиков, начиная с новых, со временем создания и решения.
+/// Пустой список состояний означает любые, id - только это сообщение
+pub async fn submissions(chat_name: &str, id: Option<i32>, statuses: &[String], offset: i64, limit: i64) -> Vec<(Submission, String, Option<String>)> {
+   let client = DB.get().unwrap();
+   let query = format!("SELECT {}, TO_CHAR(created, 'YYYY-MM-DD\"T\"HH24:MI:SS'), TO_CHAR(decided_at, 'YYYY-MM-DD\"T\"HH24:MI:SS') FROM submissions
+      WHERE chat_name = $1::VARCHAR(100) AND status <> 'draft' AND ($2::INTEGER IS NULL OR id = $2::INTEGER) AND (CARDINALITY($3::VARCHAR[]) = 0 OR status = ANY($3::VARCHAR[]))
+      ORDER BY id DESC OFFSET $4::BIGINT LIMIT $5::BIGINT", SUBMISSION_FIELDS);
+   match client.query(query.as_str(), &[&chat_name, &id, &statuses, &offset, &limit]).await {
+      Ok(rows) => rows.into_iter()
+      .map(|row| {
+         let created = row.get(row.len() - 2);
+         let decided = row.get(row.len() - 1);
+         (Submission::from(row), created, decided)
+      })
+      .collect(),
+      Err(e) => {
+         log::error!("submissions({}): {}", chat_name, e);
+         Vec::new()
+      }
+   }
+}
+
+/// Добавляет адрес для уведомлений о событиях чата, возвращает номер и секрет для проверки подписи
+pub async fn add_hook(chat_name: &str, url: &str, events: &[String]) -> Option<(i32, String)> {
+   let client = DB.get().unwrap();
+   let secret = format!("{}{}", new_token(), new_token());
+   match client.query_one("INSERT INTO hooks (chat_name, url, secret, events) VALUES ($1::VARCHAR(100), $2::TEXT, $3::VARCHAR(64), $4::VARCHAR(20)[]) RETURNING id", &[&chat_name, &url, &secret, &events]).await {
+      Ok(row) => Some((row.get(0), secret)),
+      Err(e) => {
+         log::error!("add_hook({}, {}): {}", chat_name, url, e);
+         None
+      }
+   }
+}
+
+/// Возвращает адреса для уведомлений чата с их событиями
+pub async fn hooks(chat_name: &str) -> Vec<(i32, String, Vec<String>)> {
+   let client = DB.get().unwrap();
+   match client.query("SELECT id, url, events FROM hooks WHERE chat_name = $1::VARCHAR(100) ORDER BY id", &[&chat_name]).await {
+      Ok(rows) => rows.into_iter().map(|row| (row.get(0), row.get(1), row.get(2))).collect(),
+      Err(e) => {
+         log::error!("hooks({}): {}", chat_name, e);
+         Vec::new()
+      }
+   }
+}
+
+/// Удаляет адрес для уведомлений вместе с журналом его доставки, возвращает успешность
+pub async fn delete_hook(chat_name: &str, id: i32) -> bool {
+   let client = DB.get().unwrap();
+   match client.execute("DELETE FROM hooks WHERE id = $1::INTEGER AND chat_name = $2::VARCHAR(100)", &[&id, &chat_name]).await {
+      Ok(cnt) => cnt > 0,
+      Err(e) => {
+         log::error!("delete_hook({}, {}): {}", chat_name, id, e);
+         false
+      }
+   }
+}
+
+/// Ставит уведомление в очередь доставки на адреса чата, подписанные на событие, или только
+/// на указанный адрес. Возвращает число адресов
+pub async fn enqueue_hook(chat_name: &str, hook_id: Option<i32>, event: &str, payload: &str) -> u64 {
+   let client = DB.get().unwrap();
+   match client.execute("INSERT INTO hook_deliveries (hook_id, event, payload) SELECT id, $3::VARCHAR(20), $4::TEXT FROM hooks
+      WHERE chat_name = $1::VARCHAR(100) AND ($2::INTEGER IS NULL AND $3::VARCHAR(20) = ANY(events) OR id = $2::INTEGER)", &[&chat_name, &hook_id, &event, &payload]).await {
+      Ok(cnt) => cnt,
+      Err(e) => {
+         log::error!("enqueue_hook({}, {}): {}", chat_name, event, e);
+         0
+      }
+   }
+}
+
+/// Уведомление, ожидающее доставки
+pub struct Delivery {
+   pub id: i32,
+   pub url: String,
+   pub secret: String,
+   pub event: String,
+   pub payload: String,
+   // Сколько попыток уже было
+   pub attempts: i32,
+}
+
+/// Возвращает уведомления, время доставки которых подошло
+pub async fn due_deliveries(limit: i64) -> Vec<Delivery> {
+   let client = DB.get().unwrap();
+   match client.query("SELECT d.id, h.url, h.secret, d.event, d.payload, d.attempts FROM hook_deliveries d INNER JOIN hooks h ON h.id = d.hook_id
+      WHERE d.status = 'pending' AND d.next_at <= NOW() ORDER BY d.id LIMIT $1::BIGINT", &[&limit]).await {
+      Ok(rows) => rows.into_iter()
+      .map(|row| Delivery {
+         id: row.get(0),
+         url: row.get(1),
+         secret: row.get(2),
+         event: row.get(3),
+         payload: row.get(4),
+         attempts: row.get(5),
+      })
+      .collect(),
+      Err(e) => {
+         log::error!("due_deliveries(): {}", e);
+         Vec::new()
+      }
+   }
+}
+
+/// Записывает результат попытки доставки: код ответа, ошибку и, если нужна ещё попытка,
+/// через сколько секунд её сделать. Без повтора неудачная доставка считается проваленной
+pub async fn delivery_attempt(id: i32, delivered: bool, response_code: Option<i32>, error: &str, retry_in: Option<i64>) {
+   let client = DB.get().unwrap();
+   let status = match (delivered, retry_in) {
+      (true, _) => "delivered",
+      (false, Some(_)) => "pending",
+      (false, None) => "failed",
+   };
+   let error = if error.is_empty() { None } else { Some(error) };
+   if let Err(e) = client.execute("UPDATE hook_deliveries SET status = $2::VARCHAR(10), attempts = attempts + 1, response_code = $3::INTEGER, error = $4::TEXT, next_at = NOW() + COALESCE($5::BIGINT, 0) * INTERVAL '1 second' WHERE id = $1::INTEGER", &[&id, &status, &response_code, &error, &retry_in]).await {
+      log::error!("delivery_attempt({}): {}", id, e);
+   }
+}
+
+/// Удаляет журнал доставки старше месяца
+pub async fn drop_deliveries() {
+   let client = DB.get().unwrap();
+   if let Err(e) = client.execute("DELETE FROM hook_deliveries WHERE created < NOW() - INTERVAL '30 days' AND status <> 'pending'", &[]).await {
+      log::error!("drop_deliveries(): {}", e);
+   }
+}
+
+/// Запись журнала доставки: номер адреса, событие, состояние, число попыток, код ответа,
+/// ошибка и время создания
+pub type HookLogEntry = (i32, String, String, i32, Option<i32>, Option<String>, String);
+
+/// Возвращает последние доставки уведомлений чата, начиная с новых
+pub async fn hook_log(chat_name: &str, limit: i64) -> Vec<HookLogEntry> {
+   let client = DB.get().unwrap();
+   match client.query("SELECT d.hook_id, d.event, d.status, d.attempts, d.response_code, d.error, TO_CHAR(d.created, 'DD.MM HH24:MI:SS') FROM hook_deliveries d INNER JOIN hooks h ON h.id = d.hook_id
+      WHERE h.chat_name = $1::VARCHAR(100) ORDER BY d.id DESC LIMIT $2::BIGINT", &[&chat_name, &limit]).await {
+      Ok(rows) => rows.into_iter().map(|row| (row.get(0), row.get(1), row.get(2), row.get(3), row.get(4), row.get(5), row.get(6))).collect(),
+      Err(e) => {
+         log::error!("hook_log({}): {}", chat_name, e);
+         Vec::new()
+      }
+   }
+}
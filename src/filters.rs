@@ -0,0 +1,233 @@
+/* ===============================================================================
+Бот для анонимизации сообщений для чата.
+Фильтрация сообщений до модерации. 19 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use regex::{Regex, RegexBuilder};
+use std::{fmt, str::FromStr};
+
+// Шаблоны для поиска ссылок и упоминаний
+const LINKS: &str = r"(?i)\b(?:https?://|www\.|t\.me/)\S+";
+// Имена пользователей Telegram состоят из латиницы, цифр и подчёркивания. Юникодный \w
+// с повторением превысил бы ограничение размера выражения
+const MENTIONS: &str = r"\B@[A-Za-z0-9_]{4,32}";
+
+/// Что делать с сообщением при срабатывании правила
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+   Reject,  // отклонить без модерации
+   Strip,   // вырезать совпадения
+   Flag,    // обратить внимание модератора
+}
+
+/// Что ищет правило
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+   Word,
+   Regex,
+   Links,
+   Mentions,
+}
+
+impl FromStr for Action {
+   type Err = ();
+
+   fn from_str(s: &str) -> Result<Self, Self::Err> {
+      match s {
+         "reject" => Ok(Action::Reject),
+         "strip" => Ok(Action::Strip),
+         "flag" => Ok(Action::Flag),
+         _ => Err(()),
+      }
+   }
+}
+
+impl Action {
+   pub fn as_str(&self) -> &'static str {
+      match self {
+         Action::Reject => "reject",
+         Action::Strip => "strip",
+         Action::Flag => "flag",
+      }
+   }
+}
+
+impl FromStr for Kind {
+   type Err = ();
+
+   fn from_str(s: &str) -> Result<Self, Self::Err> {
+      match s {
+         "word" => Ok(Kind::Word),
+         "regex" => Ok(Kind::Regex),
+         "links" => Ok(Kind::Links),
+         "mentions" => Ok(Kind::Mentions),
+         _ => Err(()),
+      }
+   }
+}
+
+impl Kind {
+   pub fn as_str(&self) -> &'static str {
+      match self {
+         Kind::Word => "word",
+         Kind::Regex => "regex",
+         Kind::Links => "links",
+         Kind::Mentions => "mentions",
+      }
+   }
+
+   /// Нужен ли правилу этого типа шаблон
+   pub fn has_pattern(&self) -> bool {
+      matches!(self, Kind::Word | Kind::Regex)
+   }
+}
+
+/// Правило фильтрации сообщений чата
+pub struct Rule {
+   pub id: i32,
+   pub action: Action,
+   pub kind: Kind,
+   pub pattern: String,
+}
+
+impl Rule {
+   /// Регулярное выражение, по которому ищутся совпадения
+   pub fn regex(&self) -> Result<Regex, regex::Error> {
+      let pattern = match self.kind {
+         Kind::Word => format!(r"(?i)\b{}\b", regex::escape(&self.pattern)),
+         Kind::Regex => self.pattern.clone(),
+         Kind::Links => String::from(LINKS),
+         Kind::Mentions => String::from(MENTIONS),
+      };
+
+      // Ограничим размер, чтобы владелец чата не мог нагрузить бота тяжёлым выражением
+      RegexBuilder::new(&pattern)
+      .size_limit(1 << 20)
+      .build()
+   }
+}
+
+impl fmt::Display for Rule {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      write!(f, "#{} {} {}", self.id, self.action.as_str(), self.kind.as_str())?;
+      if self.kind.has_pattern() {
+         write!(f, " {}", self.pattern)?;
+      }
+      Ok(())
+   }
+}
+
+/// Результат проверки сообщения правилами
+pub struct Verdict {
+   // Текст после вырезания совпадений
+   pub text: String,
+   // Правило, по которому сообщение отклонено
+   pub rejected: Option<String>,
+   // Пометки для модератора о сработавших правилах
+   pub notes: Vec<String>,
+}
+
+/// Проверяет сообщение правилами чата. Сначала применяются отклоняющие правила к исходному
+/// тексту, затем вырезающие и в последнюю очередь помечающие к тому, что осталось
+pub fn apply(rules: &[Rule], text: &str) -> Verdict {
+   let mut verdict = Verdict {
+      text: String::from(text),
+      rejected: None,
+      notes: Vec::new(),
+   };
+
+   for action in &[Action::Reject, Action::Strip, Action::Flag] {
+      for rule in rules.iter().filter(|rule| rule.action == *action) {
+         let re = match rule.regex() {
+            Ok(re) => re,
+            Err(e) => {
+               log::error!("filters::apply({}): {}", rule, e);
+               continue;
+            }
+         };
+
+         if !re.is_match(&verdict.text) {
+            continue;
+         }
+
+         match action {
+            Action::Reject => {
+               verdict.rejected = Some(rule.to_string());
+               return verdict;
+            }
+            Action::Strip => {
+               verdict.text = String::from(re.replace_all(&verdict.text, "").trim());
+               verdict.notes.push(format!("✂ Вырезано правилом {}", rule));
+            }
+            Action::Flag => verdict.notes.push(format!("⚠ Сработало правило {}", rule)),
+         }
+      }
+   }
+
+   verdict
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn rule(id: i32, action: Action, kind: Kind, pattern: &str) -> Rule {
+      Rule { id, action, kind, pattern: String::from(pattern) }
+   }
+
+   #[test]
+   fn no_rules() {
+      let verdict = apply(&[], "hello");
+      assert_eq!((verdict.text.as_str(), verdict.rejected, verdict.notes.len()), ("hello", None, 0));
+   }
+
+   #[test]
+   fn reject_wins_before_strip() {
+      // Отклоняющее правило проверяет исходный текст, даже если вырезающее идёт раньше
+      let rules = [rule(1, Action::Strip, Kind::Links, ""), rule(2, Action::Reject, Kind::Regex, r"t\.me/spam")];
+      let verdict = apply(&rules, "see t.me/spam now");
+      assert_eq!(verdict.rejected.as_deref(), Some("#2 reject regex t\\.me/spam"));
+      assert!(verdict.notes.is_empty());
+   }
+
+   #[test]
+   fn strip_then_flag_remainder() {
+      let rules = [
+         rule(1, Action::Flag, Kind::Links, ""),
+         rule(2, Action::Strip, Kind::Links, ""),
+         rule(3, Action::Flag, Kind::Mentions, ""),
+      ];
+      let verdict = apply(&rules, "ask @someone at https://example.com/x");
+      assert_eq!(verdict.text, "ask @someone at");
+      assert_eq!(verdict.rejected, None);
+      assert_eq!(verdict.notes, vec![String::from("✂ Вырезано правилом #2 strip links"), String::from("⚠ Сработало правило #3 flag mentions")]);
+   }
+
+   #[test]
+   fn word_matches_whole_words_case_insensitively() {
+      let rules = [rule(1, Action::Reject, Kind::Word, "spam")];
+      assert!(apply(&rules, "This is SPAM!").rejected.is_some());
+      assert!(apply(&rules, "spammer").rejected.is_none());
+      // Шаблон слова не считается регулярным выражением
+      assert!(apply(&[rule(2, Action::Reject, Kind::Word, "a.b")], "axb").rejected.is_none());
+   }
+
+   #[test]
+   fn mentions_and_emails() {
+      let rules = [rule(1, Action::Flag, Kind::Mentions, "")];
+      assert_eq!(apply(&rules, "mail me at user@example.com").notes.len(), 0);
+      assert_eq!(apply(&rules, "ping @admin_name").notes.len(), 1);
+   }
+
+   #[test]
+   fn broken_regex_is_skipped() {
+      let rules = [rule(1, Action::Reject, Kind::Regex, "("), rule(2, Action::Flag, Kind::Word, "ok")];
+      let verdict = apply(&rules, "ok (");
+      assert_eq!(verdict.rejected, None);
+      assert_eq!(verdict.notes.len(), 1);
+   }
+}
@@ -0,0 +1,229 @@
+/* ===============================================================================
+Бот для анонимизации сообщений для чата.
+Сохранение форматирования сообщений. 19 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use serde_json::{json, Value};
+use teloxide::{
+   types::{MessageEntity, MessageEntityKind},
+   utils::html,
+};
+
+// Используемая версия библиотеки не знает некоторых видов разметки и не смогла бы
+// разобрать сообщение с ними, поэтому они передаются как ссылки с особыми адресами
+const SPOILER: &str = "tg-spoiler:";
+const BLOCKQUOTE: &str = "tg-blockquote:";
+
+// Виды разметки, известные библиотеке
+const KNOWN: &[&str] = &["mention", "hashtag", "cashtag", "bot_command", "url", "email", "phone_number",
+   "bold", "italic", "code", "pre", "text_link", "text_mention", "underline", "strikethrough",
+];
+
+/// Подготавливает обновление от Telegram к разбору: неизвестная библиотеке разметка заменяется
+/// особыми ссылками или удаляется. Пользовательские эмодзи удаляются, так как по ним можно
+/// опознать автора, а вместо них остаются обычные
+pub fn prepare_update(value: &mut Value) {
+   match value {
+      Value::Object(map) => {
+         for (key, item) in map.iter_mut() {
+            match item {
+               Value::Array(list) if key == "entities" || key == "caption_entities" => {
+                  list.retain_mut(|entity| {
+                     let kind = entity.get("type").and_then(Value::as_str).unwrap_or_default();
+                     let url = match kind {
+                        "spoiler" => SPOILER,
+                        "blockquote" | "expandable_blockquote" => BLOCKQUOTE,
+                        kind => return KNOWN.contains(&kind),
+                     };
+                     entity["type"] = json!("text_link");
+                     entity["url"] = json!(url);
+                     true
+                  });
+               }
+               item => prepare_update(item),
+            }
+         }
+      }
+      Value::Array(list) => list.iter_mut().for_each(prepare_update),
+      _ => (),
+   }
+}
+
+// Экранирование для значения атрибута
+fn escape_attr(s: &str) -> String {
+   html::escape(s).replace('"', "&quot;")
+}
+
+// Открывающий и закрывающий теги для разметки, None для той, что Telegram распознаёт сам
+fn tags<U: FnMut(&str) -> String>(kind: &MessageEntityKind, clean_url: &mut U) -> Option<(String, String)> {
+   let pair = |open: &str, close: &str| Some((String::from(open), String::from(close)));
+   match kind {
+      MessageEntityKind::Bold => pair("<b>", "</b>"),
+      MessageEntityKind::Italic => pair("<i>", "</i>"),
+      MessageEntityKind::Underline => pair("<u>", "</u>"),
+      MessageEntityKind::Strikethrough => pair("<s>", "</s>"),
+      MessageEntityKind::Code => pair("<code>", "</code>"),
+      MessageEntityKind::Pre { language: Some(language) } => Some((format!("<pre><code class=\"language-{}\">", escape_attr(language)), String::from("</code></pre>"))),
+      MessageEntityKind::Pre { language: None } => pair("<pre>", "</pre>"),
+      MessageEntityKind::TextLink { url } if url == SPOILER => pair("<tg-spoiler>", "</tg-spoiler>"),
+      MessageEntityKind::TextLink { url } if url == BLOCKQUOTE => pair("<blockquote>", "</blockquote>"),
+      MessageEntityKind::TextLink { url } => Some((format!("<a href=\"{}\">", escape_attr(&clean_url(url))), String::from("</a>"))),
+      MessageEntityKind::TextMention { user } => Some((format!("<a href=\"tg://user?id={}\">", user.id), String::from("</a>"))),
+      _ => None,
+   }
+}
+
+/// Преобразует текст с разметкой Telegram в HTML. Каждый кусок текста между границами разметки
+/// очищается функцией clean, адреса ссылок функцией clean_url. Возвращает HTML и простой текст
+pub fn to_html<F, U>(text: &str, entities: &[MessageEntity], mut clean: F, mut clean_url: U) -> (String, String)
+where
+   F: FnMut(&str) -> String,
+   U: FnMut(&str) -> String,
+{
+   let chars: Vec<char> = text.chars().collect();
+
+   // Смещения разметки указаны в единицах UTF-16, переведём их в номера символов
+   let mut utf16 = Vec::with_capacity(chars.len() + 1);
+   let mut pos = 0;
+   for c in &chars {
+      utf16.push(pos);
+      pos += c.len_utf16();
+   }
+   utf16.push(pos);
+   let index = |offset: usize| utf16.partition_point(|&p| p < offset);
+
+   // Разметка с границами в символах, внешняя раньше вложенной
+   let mut spans: Vec<(usize, usize, String, String)> = entities.iter()
+   .filter_map(|entity| {
+      let (open, close) = tags(&entity.kind, &mut clean_url)?;
+      let start = index(entity.offset);
+      let end = index(entity.offset + entity.length);
+      if start < end { Some((start, end, open, close)) } else { None }
+   })
+   .collect();
+   spans.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+
+   // Все границы кусков текста
+   let mut bounds: Vec<usize> = spans.iter().flat_map(|span| vec![span.0, span.1]).collect();
+   bounds.push(0);
+   bounds.push(chars.len());
+   bounds.sort_unstable();
+   bounds.dedup();
+
+   let mut html = String::new();
+   let mut plain = String::new();
+   // Открытая разметка: конец, открывающий и закрывающий теги
+   let mut stack: Vec<(usize, String, String)> = Vec::new();
+   let mut next_span = spans.into_iter().peekable();
+
+   for (i, &bound) in bounds.iter().enumerate() {
+      // Закрываем завершившуюся разметку. Если она пересекается с вложенной, а не содержит
+      // её целиком, вложенную придётся закрыть вместе с ней и открыть снова
+      if let Some(first) = stack.iter().position(|(end, _, _)| *end <= bound) {
+         let above = stack.split_off(first);
+         for (_, _, close) in above.iter().rev() {
+            html.push_str(close);
+         }
+         for span in above.into_iter().filter(|(end, _, _)| *end > bound) {
+            html.push_str(&span.1);
+            stack.push(span);
+         }
+      }
+
+      // Открываем начинающуюся
+      while let Some((_, end, open, close)) = next_span.next_if(|span| span.0 == bound) {
+         html.push_str(&open);
+         stack.push((end, open, close));
+      }
+
+      // Кусок текста до следующей границы
+      if let Some(&next) = bounds.get(i + 1) {
+         let piece: String = chars[bound..next].iter().collect();
+         let piece = clean(&piece);
+         html.push_str(&html::escape(&piece));
+         plain.push_str(&piece);
+      }
+   }
+
+   // Незакрытое из-за ошибок в разметке
+   while let Some((_, _, close)) = stack.pop() {
+      html.push_str(&close);
+   }
+
+   (html, plain)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn entity(kind: MessageEntityKind, offset: usize, length: usize) -> MessageEntity {
+      MessageEntity::new(kind, offset, length)
+   }
+
+   fn convert(text: &str, entities: &[MessageEntity]) -> (String, String) {
+      to_html(text, entities, |piece| piece.to_string(), |url| url.to_string())
+   }
+
+   #[test]
+   fn plain_text_is_escaped() {
+      assert_eq!(convert("a < b & c", &[]), (String::from("a &lt; b &amp; c"), String::from("a < b & c")));
+   }
+
+   #[test]
+   fn nested_entities() {
+      let entities = [entity(MessageEntityKind::Bold, 0, 11), entity(MessageEntityKind::Italic, 6, 5)];
+      assert_eq!(convert("hello world", &entities).0, "<b>hello <i>world</i></b>");
+   }
+
+   #[test]
+   fn overlapping_entities_are_reopened() {
+      // Жирный 0..5 и курсив 3..8 пересекаются
+      let entities = [entity(MessageEntityKind::Bold, 0, 5), entity(MessageEntityKind::Italic, 3, 5)];
+      assert_eq!(convert("abcdefgh", &entities).0, "<b>abc<i>de</i></b><i>fgh</i>");
+   }
+
+   #[test]
+   fn overlapping_entities_keep_outer_open() {
+      // Внешний 0..8, пересекающиеся 1..4 и 2..6 внутри него
+      let entities = [
+         entity(MessageEntityKind::Bold, 0, 8),
+         entity(MessageEntityKind::Italic, 1, 3),
+         entity(MessageEntityKind::Underline, 2, 4),
+      ];
+      assert_eq!(convert("abcdefgh", &entities).0, "<b>a<i>b<u>cd</u></i><u>ef</u>gh</b>");
+   }
+
+   #[test]
+   fn utf16_offsets() {
+      // Эмодзи занимает две единицы UTF-16
+      let entities = [entity(MessageEntityKind::Bold, 3, 2)];
+      assert_eq!(convert("😀 ab", &entities).0, "😀 <b>ab</b>");
+   }
+
+   #[test]
+   fn links_and_special_entities() {
+      let entities = [
+         entity(MessageEntityKind::TextLink { url: String::from("https://example.com/?a=\"b\"") }, 0, 4),
+         entity(MessageEntityKind::TextLink { url: String::from(SPOILER) }, 5, 4),
+      ];
+      assert_eq!(convert("link text", &entities).0, "<a href=\"https://example.com/?a=&quot;b&quot;\">link</a> <tg-spoiler>text</tg-spoiler>");
+   }
+
+   #[test]
+   fn clean_is_applied_to_pieces() {
+      let entities = [entity(MessageEntityKind::Bold, 0, 2)];
+      let (html, plain) = to_html("ab cd", &entities, |piece| piece.to_uppercase(), |url| url.to_string());
+      assert_eq!((html.as_str(), plain.as_str()), ("<b>AB</b> CD", "AB CD"));
+   }
+
+   #[test]
+   fn empty_and_unknown_entities_are_skipped() {
+      let entities = [entity(MessageEntityKind::Bold, 1, 0), entity(MessageEntityKind::Hashtag, 0, 3)];
+      assert_eq!(convert("#ab", &entities).0, "#ab");
+   }
+}
@@ -0,0 +1,360 @@
+/* ===============================================================================
+Бот для анонимизации сообщений для чата.
+Уведомления о событиях модерации на адреса администратора. 19 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use std::{env, net::{IpAddr, SocketAddr}, str::FromStr};
+use futures::future::join_all;
+use reqwest::{redirect, Client, Url};
+use serde_json::{json, Value};
+use tokio::{net::lookup_host, time::{sleep, Duration}};
+
+use crate::database as db;
+use crate::signing::hmac_sha256;
+
+// Сколько всего попыток доставки, после последней неудачной уведомление считается недоставленным
+const MAX_ATTEMPTS: i32 = 6;
+
+// Пауза перед первым повтором, каждая следующая вчетверо длиннее: 30 сек., 2 мин., 8 мин. и т.д.
+const FIRST_RETRY: i64 = 30;
+
+// Сколько ждать ответа адреса
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Сколько уведомлений доставлять за один проход
+const BATCH: i64 = 20;
+
+/// Событие модерации
+#[derive(Clone, Copy, PartialEq)]
+pub enum Event {
+   // Автор подтвердил отправку
+   Received,
+   Approved,
+   // Отклонено модераторами или снято по сроку
+   Rejected,
+   Published,
+   PublishFailed,
+   // Проверка адреса по команде администратора
+   Ping,
+}
+
+/// События, на которые можно подписаться
+pub const EVENTS: [Event; 5] = [Event::Received, Event::Approved, Event::Rejected, Event::Published, Event::PublishFailed];
+
+impl FromStr for Event {
+   type Err = String;
+
+   fn from_str(s: &str) -> Result<Self, Self::Err> {
+      EVENTS.iter()
+      .find(|event| event.as_str() == s)
+      .copied()
+      .ok_or_else(|| format!("Неизвестное событие {}, доступны {}", s, EVENTS.iter().map(Event::as_str).collect::<Vec<_>>().join(", ")))
+   }
+}
+
+impl Event {
+   pub fn as_str(&self) -> &'static str {
+      match self {
+         Event::Received => "received",
+         Event::Approved => "approved",
+         Event::Rejected => "rejected",
+         Event::Published => "published",
+         Event::PublishFailed => "publish_failed",
+         Event::Ping => "ping",
+      }
+   }
+}
+
+/// Разрешены ли адреса во внутренней сети и http, например для проверки на своём компьютере.
+/// Задаётся переменной окружения HOOKS_ALLOW_PRIVATE
+pub fn allow_private() -> bool {
+   env::var("HOOKS_ALLOW_PRIVATE").is_ok_and(|value| !value.is_empty() && value != "0")
+}
+
+/// Проверяет вид адреса для уведомлений, нужен https
+pub fn check_url(url: &str, allow_private: bool) -> Result<Url, String> {
+   let url = Url::parse(url).map_err(|e| format!("Неверный адрес {}: {}", url, e))?;
+   match url.scheme() {
+      "https" if url.host().is_some() => Ok(url),
+      "http" if url.host().is_some() && allow_private => Ok(url),
+      _ => Err(String::from("Адрес должен начинаться с https://")),
+   }
+}
+
+// Адрес в интернете, а не в локальной или служебной сети, в том числе 169.254.169.254
+// с метаданными облачных серверов
+fn is_public(ip: IpAddr) -> bool {
+   match ip {
+      IpAddr::V4(ip) => {
+         let [a, b, ..] = ip.octets();
+         !(ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified() || ip.is_broadcast()
+            || ip.is_multicast() || ip.is_documentation()
+            || a == 0                              // "эта" сеть
+            || (a == 100 && (64..128).contains(&b))  // общий адрес провайдера
+            || (a == 198 && (b == 18 || b == 19))    // тестирование производительности
+            || a >= 240)                           // зарезервированные
+      }
+      IpAddr::V6(ip) => {
+         if let Some(ip) = ip.to_ipv4_mapped() {
+            return is_public(IpAddr::V4(ip));
+         }
+         let first = ip.segments()[0];
+         !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast()
+            || (first & 0xfe00) == 0xfc00            // уникальные локальные
+            || (first & 0xffc0) == 0xfe80            // локальные в канале
+            || first == 0x2001 && ip.segments()[1] == 0xdb8  // документация
+            || first == 0x0064)                    // преобразование в IPv4
+      }
+   }
+}
+
+/// Находит IP-адрес сервера и проверяет, что он не во внутренней сети
+pub async fn resolve(url: &Url, allow_private: bool) -> Result<SocketAddr, String> {
+   let host = url.host_str().unwrap_or_default().trim_start_matches('[').trim_end_matches(']');
+   let port = url.port_or_known_default().unwrap_or(443);
+   let addrs: Vec<SocketAddr> = lookup_host((host, port)).await
+   .map_err(|e| format!("Не удалось найти сервер {}: {}", host, e))?
+   .collect();
+   if !allow_private && addrs.iter().any(|addr| !is_public(addr.ip())) {
+      return Err(format!("Сервер {} находится во внутренней сети, уведомления туда не отправляются", host));
+   }
+   addrs.into_iter().next().ok_or_else(|| format!("Не удалось найти сервер {}", host))
+}
+
+/// Проверяет адрес для уведомлений при добавлении
+pub async fn check(url: &str) -> Result<Url, String> {
+   let allow_private = allow_private();
+   let url = check_url(url, allow_private)?;
+   resolve(&url, allow_private).await?;
+   Ok(url)
+}
+
+// Тело уведомления
+fn payload(event: Event, chat_name: &str, submission: Option<Value>, error: Option<&str>) -> String {
+   json!({
+      "event": event.as_str(),
+      "chat": chat_name,
+      "time": chrono::Utc::now().to_rfc3339(),
+      "submission": submission,
+      "error": error,
+   })
+   .to_string()
+}
+
+/// Ставит уведомление о событии сообщения в очередь доставки, error - пояснение для
+/// неудачной публикации. Состояние сообщения берётся актуальное, автор не раскрывается
+pub async fn fire(event: Event, submission: &db::Submission, error: Option<&str>) {
+   let fresh = db::submission(submission.id).await;
+   let submission = fresh.as_ref().unwrap_or(submission);
+   let data = json!({
+      "id": submission.id,
+      "status": submission.status,
+      "mode": submission.mode,
+      "text": submission.text,
+      "html": submission.text_html(),
+      "thread": submission.thread,
+      "published_id": submission.published_id,
+   });
+   db::enqueue_hook(&submission.chat_name, None, event.as_str(), &payload(event, &submission.chat_name, Some(data), error)).await;
+}
+
+/// Ставит в очередь проверочное уведомление на указанный адрес чата, возвращает успешность
+pub async fn ping(chat_name: &str, hook_id: i32) -> bool {
+   db::enqueue_hook(chat_name, Some(hook_id), Event::Ping.as_str(), &payload(Event::Ping, chat_name, None, None)).await > 0
+}
+
+/// Доставляет уведомления из очереди, повторяя неудачные с нарастающей паузой
+pub async fn dispatcher() {
+   loop {
+      sleep(Duration::from_secs(5)).await;
+
+      // Старый журнал
+      db::drop_deliveries().await;
+
+      // Доставляем одновременно, чтобы медленный адрес не задерживал уведомления других чатов
+      let allow_private = allow_private();
+      join_all(db::due_deliveries(BATCH).await.iter().map(|delivery| async move {
+         let attempt = delivery.attempts + 1;
+         let (delivered, code, error) = send(delivery, allow_private).await;
+         db::delivery_attempt(delivery.id, delivered, code, &error, retry_in(attempt, delivered)).await;
+      })).await;
+   }
+}
+
+// Пауза перед следующей попыткой после попытки с этим номером (с единицы), None - больше не пытаться
+fn retry_in(attempt: i32, delivered: bool) -> Option<i64> {
+   if delivered || attempt >= MAX_ATTEMPTS {
+      None
+   } else {
+      Some(FIRST_RETRY * 4i64.pow(attempt as u32 - 1))
+   }
+}
+
+// Отправляет уведомление с подписью тела в заголовке, возвращает успешность, код ответа и ошибку.
+// Адрес проверяется заново, так как сервер мог сменить IP-адрес, и запрос идёт именно на
+// проверенный IP-адрес без перехода по перенаправлениям
+async fn send(delivery: &db::Delivery, allow_private: bool) -> (bool, Option<i32>, String) {
+   let checked = match check_url(&delivery.url, allow_private) {
+      Ok(url) => resolve(&url, allow_private).await.map(|addr| (url, addr)),
+      Err(e) => Err(e),
+   };
+   let (url, addr) = match checked {
+      Ok(checked) => checked,
+      Err(e) => return (false, None, e),
+   };
+   let client = Client::builder()
+   .timeout(TIMEOUT)
+   .redirect(redirect::Policy::none())
+   .resolve(url.host_str().unwrap_or_default(), addr)
+   .build();
+   let client = match client {
+      Ok(client) => client,
+      Err(e) => return (false, None, e.to_string()),
+   };
+
+   let signature = hex::encode(hmac_sha256(delivery.secret.as_bytes(), delivery.payload.as_bytes()));
+   let res = client.post(url)
+   .header("Content-Type", "application/json")
+   .header("X-Cognito-Event", &delivery.event)
+   .header("X-Cognito-Delivery", delivery.id.to_string())
+   .header("X-Cognito-Signature", format!("sha256={}", signature))
+   .body(delivery.payload.clone())
+   .send()
+   .await;
+
+   match res {
+      Ok(response) => {
+         let status = response.status();
+         let error = if status.is_success() { String::new() } else { format!("HTTP {}", status) };
+         (status.is_success(), Some(i32::from(status.as_u16())), error)
+      }
+      Err(e) => (false, None, e.to_string()),
+   }
+}
+
+/// Строка журнала доставки для администратора
+pub fn log_line(entry: &db::HookLogEntry) -> String {
+   let (hook_id, event, status, attempts, code, error, created) = entry;
+   let code = code.map(|code| format!(", ответ {}", code)).unwrap_or_default();
+   let error = error.as_ref().map(|error| format!(", {}", error)).unwrap_or_default();
+   format!("{} #{} {}: {}, попыток {}{}{}", created, hook_id, event, status, attempts, code, error)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use std::sync::{Arc, Mutex};
+   use warp::{http::{HeaderMap, StatusCode}, Filter};
+
+   // Полученный тестовым сервером запрос: заголовки и тело
+   type Received = Arc<Mutex<Vec<(HeaderMap, String)>>>;
+
+   // Локальный сервер вместо адреса администратора, отвечает указанным кодом
+   fn stand_in(status: StatusCode) -> (SocketAddr, Received) {
+      let received: Received = Arc::new(Mutex::new(Vec::new()));
+      let store = received.clone();
+      let route = warp::post()
+      .and(warp::header::headers_cloned())
+      .and(warp::body::bytes())
+      .map(move |headers: HeaderMap, body: warp::hyper::body::Bytes| {
+         store.lock().unwrap().push((headers, String::from_utf8_lossy(&body).into_owned()));
+         warp::reply::with_header(warp::reply::with_status("", status), "Location", "http://127.0.0.1:9/elsewhere")
+      });
+      let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+      tokio::spawn(server);
+      (addr, received)
+   }
+
+   fn delivery(url: String) -> db::Delivery {
+      db::Delivery {
+         id: 7,
+         url,
+         secret: String::from("secret"),
+         event: String::from("approved"),
+         payload: String::from(r#"{"event":"approved"}"#),
+         attempts: 0,
+      }
+   }
+
+   #[test]
+   fn url_scheme() {
+      assert!(check_url("https://example.com/hook", false).is_ok());
+      assert!(check_url("http://example.com/hook", false).is_err());
+      assert!(check_url("http://127.0.0.1:8080/hook", true).is_ok());
+      assert!(check_url("ftp://example.com/hook", true).is_err());
+      assert!(check_url("example.com/hook", false).is_err());
+   }
+
+   #[test]
+   fn public_addresses() {
+      for ip in ["8.8.8.8", "1.1.1.1", "2a00:1450:4001:800::200e"] {
+         assert!(is_public(ip.parse().unwrap()), "{}", ip);
+      }
+      for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "0.0.0.0", "100.64.0.1",
+         "255.255.255.255", "224.0.0.1", "::1", "::", "fd00::1", "fe80::1", "::ffff:127.0.0.1", "::ffff:169.254.169.254"] {
+         assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+      }
+   }
+
+   #[tokio::test]
+   async fn private_hosts_are_rejected() {
+      for url in ["https://127.0.0.1/hook", "https://169.254.169.254/latest/meta-data", "https://[::1]/hook", "https://10.0.0.1:8443/hook", "https://localhost/hook"] {
+         let url = check_url(url, false).unwrap();
+         assert!(resolve(&url, false).await.is_err(), "{}", url);
+         assert!(resolve(&url, true).await.is_ok(), "{}", url);
+      }
+   }
+
+   #[tokio::test]
+   async fn delivery_is_signed() {
+      let (addr, received) = stand_in(StatusCode::OK);
+      let delivery = delivery(format!("http://{}/hook", addr));
+      assert_eq!(send(&delivery, true).await, (true, Some(200), String::new()));
+
+      let received = received.lock().unwrap();
+      let (headers, body) = &received[0];
+      assert_eq!(body, &delivery.payload);
+      assert_eq!(headers["X-Cognito-Event"], "approved");
+      assert_eq!(headers["X-Cognito-Delivery"], "7");
+      let expected = format!("sha256={}", hex::encode(hmac_sha256(b"secret", delivery.payload.as_bytes())));
+      assert_eq!(headers["X-Cognito-Signature"], expected.as_str());
+   }
+
+   #[tokio::test]
+   async fn failures_are_reported() {
+      let (addr, _) = stand_in(StatusCode::INTERNAL_SERVER_ERROR);
+      let (delivered, code, error) = send(&delivery(format!("http://{}/hook", addr)), true).await;
+      assert_eq!((delivered, code), (false, Some(500)));
+      assert!(error.starts_with("HTTP 500"), "{}", error);
+
+      // Перенаправления не выполняются
+      let (addr, received) = stand_in(StatusCode::FOUND);
+      assert_eq!(send(&delivery(format!("http://{}/hook", addr)), true).await.1, Some(302));
+      assert_eq!(received.lock().unwrap().len(), 1);
+
+      // Адрес во внутренней сети без разрешения
+      let (addr, received) = stand_in(StatusCode::OK);
+      let (delivered, code, _) = send(&delivery(format!("http://{}/hook", addr)), false).await;
+      assert_eq!((delivered, code), (false, None));
+      assert!(received.lock().unwrap().is_empty());
+   }
+
+   #[test]
+   fn retry_schedule() {
+      let schedule: Vec<Option<i64>> = (1..=MAX_ATTEMPTS).map(|attempt| retry_in(attempt, false)).collect();
+      assert_eq!(schedule, vec![Some(30), Some(120), Some(480), Some(1920), Some(7680), None]);
+      assert_eq!(retry_in(1, true), None);
+   }
+
+   #[test]
+   fn delivery_log() {
+      let failed = (2, String::from("approved"), String::from("pending"), 1, Some(500), Some(String::from("HTTP 500")), String::from("19.10 12:00:00"));
+      assert_eq!(log_line(&failed), "19.10 12:00:00 #2 approved: pending, попыток 1, ответ 500, HTTP 500");
+      let delivered = (2, String::from("ping"), String::from("delivered"), 1, Some(200), None, String::from("19.10 12:01:00"));
+      assert_eq!(log_line(&delivered), "19.10 12:01:00 #2 ping: delivered, попыток 1, ответ 200");
+   }
+}
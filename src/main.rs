@@ -10,51 +10,171 @@ Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
 use teloxide::{
    prelude::*,
    utils::command::BotCommand,
-   types::{ChatId, InlineKeyboardMarkup, InlineKeyboardButton, CallbackQuery,},
+   types::{Chat, ChatId, CallbackQuery, ParseMode,},
+   utils::html,
    requests::ResponseResult,
    dispatching::{update_listeners::{self, StatefulListener}, stop_token::AsyncStopToken}
 };
-use std::{convert::Infallible, env, net::SocketAddr, };
-use tokio::{sync::mpsc, time::{sleep, Duration}};
+use std::{convert::Infallible, env, net::SocketAddr, time::Instant};
+use tokio::sync::mpsc;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use warp::Filter;
 use reqwest::{StatusCode, Url};
-use rand::Rng;
 use native_tls::{TlsConnector};
 use postgres_native_tls::MakeTlsConnector;
 
+mod api;
 mod database;
 use database as db;
+mod filters;
+mod hooks;
+mod media;
+mod metrics;
+mod formatting;
+mod moderation;
+mod picker;
+mod poll;
+mod quorum;
+mod reply;
+mod sanitize;
+mod settings;
+mod signing;
+mod timing;
+mod topics;
+mod web;
 
 #[derive(BotCommand)]
 #[command(rename = "lowercase", description = "Поддерживаются команды:")]
 enum Command {
-   Start,
+   Start(String),
    #[command(description = "выводит этот текст.")]
    Help,
-   #[command(description = "регистрация новой публичной группы, например для группы t.me/your_chat надо отправить '/register @your_chat', бот должен быть добавлен в этот чат, иначе он не сможет отправлять сообщения. Вы можете быть администратором только одного чата, при регистрации нового предыдущий будет забыт.")]
+   #[command(description = "регистрация новой публичной группы, например для группы t.me/your_chat надо отправить '/register @your_chat', бот должен быть добавлен в этот чат, иначе он не сможет отправлять сообщения. Так же регистрируется и канал, в нём бот должен быть администратором с правом публикации сообщений. Вы можете быть администратором только одного чата, при регистрации нового предыдущий будет забыт.")]
    Register(String),
    #[command(description = "указание боту забыть чат.")]
    Unregister,
+   #[command(description = "список заблокированных авторов вашего чата.")]
+   Banlist,
+   #[command(description = "снять блокировку с автора по номеру из списка, например '/unban 5'.")]
+   Unban(String),
+   #[command(description = "добавить правило фильтрации сообщений для вашего чата: '/filter действие тип шаблон', где действие reject (отклонить без модерации), strip (вырезать совпадения) или flag (пометить для модератора), а тип word (слово), regex (регулярное выражение), links (ссылки) или mentions (упоминания), например '/filter reject word казино' или '/filter strip links'.")]
+   Filter(String),
+   #[command(description = "список правил фильтрации вашего чата.")]
+   Filters,
+   #[command(description = "удалить правило фильтрации по номеру из списка, например '/unfilter 3'.")]
+   Unfilter(String),
+   #[command(description = "отозвать своё сообщение: ожидающее модерации не будет опубликовано, а опубликованное будет удалено из чата.")]
+   Retract,
+   #[command(description = "настройки вашего чата.")]
+   Settings,
+   #[command(description = "изменить настройку вашего чата: '/set название значение', например '/set mode auto', список настроек выводит /settings.")]
+   Set(String),
+   #[command(description = "создать ссылку-приглашение в ваш чат, по ней чат будет выбран для отправки, даже если он скрыт настройкой visibility.")]
+   Invite,
+   #[command(description = "список приглашений в ваш чат.")]
+   Invites,
+   #[command(description = "отозвать приглашение по номеру из списка, принявшие его потеряют доступ к закрытому чату, например '/revoke 2'.")]
+   Revoke(String),
+   #[command(description = "ссылка на ваш чат: перешедшие по ней будут отправлять сообщения сразу в него, '/link new' заменит ссылку на новую, а прежняя перестанет действовать.")]
+   Link(String),
+   #[command(description = "чат по умолчанию: '/target @chat' - отправлять сообщения сразу в него без выбора из списка, '/target off' - снова выбирать из списка, без параметров выводит текущий.")]
+   Target(String),
+   #[command(description = "анонимный опрос: '/poll вопрос', а с новых строк варианты ответа, подробнее - /poll без параметров.")]
+   Poll(String),
+   #[command(description = "разрешить отправку в тему форума вашего чата: '/topic номер название', номер темы - последнее число в ссылке на её первое сообщение, например '/topic 12 Новости'. Если темы заданы, автор выбирает одну из них.")]
+   Topic(String),
+   #[command(description = "список тем форума вашего чата, доступных для отправки.")]
+   Topics,
+   #[command(description = "убрать тему форума из доступных по номеру, например '/untopic 12'.")]
+   Untopic(String),
+   #[command(description = "группа модераторов: отправьте '/modchat' в группе, где есть бот, и сообщения на модерацию будут приходить туда, решение сможет принять любой её участник. '/modchat off' здесь вернёт модерацию к вам в личку.")]
+   Modchat(String),
+   #[command(description = "запасные модераторы: выводит ссылку, перешедшие по которой будут получать сообщения, не рассмотренные за время из настройки sla, '/backups new' забудет всех запасных модераторов и заменит ссылку.")]
+   Backups(String),
+   #[command(description = "сообщения, ждущие решения модераторов ваших чатов, с кнопками решения и перехода между ними. В группе модераторов выводит очередь модерируемых в ней чатов.")]
+   Queue,
+   #[command(description = "токен для доступа интеграций к вашему чату через API, описание API по адресу /api/openapi.json на сервере бота. Каждый вызов заменяет токен на новый, '/apitoken off' отзывает его.")]
+   Apitoken(String),
+   #[command(description = "уведомлять о событиях модерации вашего чата: '/hook адрес события', события received, approved, rejected, published и publish_failed через пробел, без них - все. Уведомление приходит POST-запросом с JSON, подписанным HMAC-SHA256 в заголовке X-Cognito-Signature, неудачные повторяются.")]
+   Hook(String),
+   #[command(description = "адреса для уведомлений вашего чата, '/hooks log' - журнал доставки, '/hooks ping номер' - отправить проверочное уведомление.")]
+   Hooks(String),
+   #[command(description = "удалить адрес для уведомлений по номеру из списка, например '/unhook 2'.")]
+   Unhook(String),
 }
 
+// Имя бота для команд и ссылок
+const BOT_NAME: &str = "cognito_bot";
+
+// Ответ пользователю, у которого нет зарегистрированного чата
+const NO_CHAT: &str = "Зарегистрированного вами чата не числится";
+
 async fn handle_message(cx: UpdateWithCx<AutoSend<Bot>, Message>) -> ResponseResult<Message> {
 
    // Для различения, в личку или в группу пишут
    let chat_id = cx.update.chat_id();
 
-   // Обрабатываем сообщение, только если оно пришло в личку
+   // Обрабатываем сообщение, только если оно пришло в личку, в группах бот понимает только команды модераторов
    if chat_id < 0 {
-      return Ok(cx.update);
+      return match cx.update.text().map(|text| Command::parse(text, BOT_NAME)) {
+         Some(Ok(Command::Queue)) => show_queue(&cx).await,
+         Some(Ok(Command::Modchat(_))) => {
+            let res = match cx.update.from() {
+               Some(user) => group_modchat(user.id, &cx.update.chat).await,
+               None => String::from("Отправьте команду от своего имени, а не от имени группы"),
+            };
+            cx.answer(res).await
+         }
+         _ => Ok(cx.update),
+      };
    }
    
    match cx.update.text() {
-      None => cx.answer("Текстовое сообщение, пожалуйста!").await,
+      // Части альбома собираются вместе, дальше идёт только одна из них
+      None if cx.update.media_group_id().is_some() => {
+         if media::collect(&cx.update).await {
+            offer(&cx).await
+         } else {
+            Ok(cx.update)
+         }
+      }
+      // Опрос и вложения, как и текст, отправляются в выбранный чат
+      None => offer(&cx).await,
       Some(text) => {
          // Попробуем получить команду
-         if let Ok(command) = Command::parse(text, "cognito_bot") {
+         if let Ok(command) = Command::parse(text, BOT_NAME) {
             match command {
-               Command::Start => cx.answer(String::from("Добро пожаловать. Отправьте сообщение, выберите чат из списка зарегистрированных в боте и оно будет направлено на модерацию администратору чата (он не будет знать, от кого). Если администратор одобрит его публикацию, сообщение будет отправлено ботом в чат также анонимно. Все поддерживаемые команды: /help")).await,
+               Command::Start(payload) if payload.starts_with("chat_") => {
+                  let user_id = cx.update.from().unwrap().id;
+                  let res = match db::accept_invite(user_id, payload.trim_start_matches("chat_")).await {
+                     Some(chat_name) => format!("Вы приглашены в чат {}. {}", chat_name, target_hint(&chat_name)),
+                     None => String::from("Приглашение недействительно, возможно администратор его отозвал"),
+                  };
+                  cx.answer(res).await
+               }
+               Command::Start(payload) if payload.starts_with("mod_") => {
+                  let user = cx.update.from().unwrap();
+                  let name = user.mention().unwrap_or_else(|| user.full_name());
+                  let res = match db::join_backups(user.id, &name, payload.trim_start_matches("mod_")).await {
+                     Some(chat_name) => format!("Вы запасной модератор чата {}, сюда будут приходить сообщения, которые модераторы не рассмотрели вовремя", chat_name),
+                     None => String::from("Ссылка недействительна, возможно администратор чата её заменил"),
+                  };
+                  cx.answer(res).await
+               }
+               Command::Start(payload) if !payload.is_empty() => {
+                  let user_id = cx.update.from().unwrap().id;
+                  let res = match db::follow_link(user_id, payload.trim()).await {
+                     Some((chat_name, true)) => target_hint(&chat_name),
+                     Some((chat_name, false)) => format!("Чат {} принимает сообщения только по приглашению его администратора", chat_name),
+                     None => String::from("Ссылка недействительна, возможно администратор чата её заменил"),
+                  };
+                  cx.answer(res).await
+               }
+               Command::Start(_) => {
+                  // Без ссылки возвращаемся к выбору чата из списка
+                  db::clear_target(cx.update.from().unwrap().id).await;
+                  cx.answer(String::from("Добро пожаловать. Отправьте сообщение, выберите чат из списка зарегистрированных в боте и оно будет направлено на модерацию администратору чата (он не будет знать, от кого). Если администратор одобрит его публикацию, сообщение будет отправлено ботом в чат также анонимно. Чтобы ответить на сообщение в чате, вставьте первой строкой ссылку на него. Все поддерживаемые команды: /help")).await
+               }
                Command::Help => cx.answer(Command::descriptions()).await,
                Command::Register(chat_name) => {
                   let res = if chat_name.is_empty() {String::from("После команды /register надо указать имя чата, например если имя вашего чата @your_chat, то введите вручную и отправьте отдельным сообщением /register @your_chat")}
@@ -65,20 +185,31 @@ async fn handle_message(cx: UpdateWithCx<AutoSend<Bot>, Message>) -> ResponseRes
                         if db::user_id(&chat_name.clone()).await.is_some() {
                            String::from("Такой чат уже зарегистрирован")
                         } else {
-                           // Пробуем отправить приветственное сообщение в чат
+                           // Канал проверим по правам бота, а в группу пробуем отправить приветственное сообщение
                            let chat_id = ChatId::ChannelUsername(chat_name.clone());
-                           let res = cx.requester
-                           .send_message(chat_id, "Приветствую вас. Я бот-анонимайзер, напишите мне в личку, я от своего имени перешлю сообщение админу и если он одобрит, я от своего имени перешлю его сюда и никто, кроме вас самого, не будет знать, от кого оно")
-                           .send()
-                           .await;
+                           let res = match cx.requester.get_chat(chat_id.clone()).send().await {
+                              Ok(chat) if chat.is_channel() => check_channel(&cx.requester, chat_id).await.map(|_| true),
+                              Ok(_) => cx.requester
+                                 .send_message(chat_id, "Приветствую вас. Я бот-анонимайзер, напишите мне в личку, я от своего имени перешлю сообщение админу и если он одобрит, я от своего имени перешлю его сюда и никто, кроме вас самого, не будет знать, от кого оно")
+                                 .send()
+                                 .await
+                                 .map(|_| false)
+                                 .map_err(|e| format!("Не удалось отправить сообщение в чат, возможно вы забыли меня в него добавить: {}", e)),
+                              Err(e) => Err(format!("Не удалось найти чат {}, проверьте его имя: {}", chat_name, e)),
+                           };
                            match res {
-                              Ok(_) => {
+                              Ok(channel) => {
                                  // Всё хорошо, сохраним регистрацию
                                  let user_id = cx.update.from().unwrap().id;
                                  db::register(user_id, chat_name).await;
-                                 String::from("Регистрация успешна. Если бот не сможет отправить сообщение в чат или его услугами не будут пользоваться более 3-х месяцев, информация о нём будет стёрта, но вы всегда сможете зарегистрировать его заново")
+                                 let res = String::from("Регистрация успешна. Если бот не сможет отправить сообщение в чат или его услугами не будут пользоваться более 3-х месяцев, регистрация будет снята, но его настройки, правила и блокировки сохранятся и вы всегда сможете зарегистрировать его заново");
+                                 if channel {
+                                    format!("{}\n\nПодпись публикаций, публикацию без звука и кнопку перехода к обсуждению можно настроить, подробнее в /settings", res)
+                                 } else {
+                                    res
+                                 }
                               }
-                              Err(e) => format!("Не удалось отправить сообщение в чат, возможно вы забыли меня в него добавить: {}", e)
+                              Err(e) => e,
                            }
                         }
                      }
@@ -95,18 +226,614 @@ async fn handle_message(cx: UpdateWithCx<AutoSend<Bot>, Message>) -> ResponseRes
                         db::unregister(user_id).await;
                         format!("Информация о чате {} удалена", chat_name)
                      }
-                     None => String::from("Зарегистрированного вами чата не числится, если вы его регистрировали, то возможно он был удалён автоматически при ошибке отправки в него сообщений или из-за долгого бездействия")
+                     None => {
+                        // Данные чатов, забытых после ошибок отправки, удаляются тоже
+                        let gone = db::unregister(user_id).await;
+                        if gone.is_empty() {
+                           String::from("Зарегистрированного вами чата не числится, если вы его регистрировали, то возможно он был удалён автоматически при ошибке отправки в него сообщений или из-за долгого бездействия")
+                        } else {
+                           format!("Зарегистрированного вами чата не числится, сохранённая информация о ранее зарегистрированных {} удалена", gone.join(", "))
+                        }
+                     }
+                  };
+                  cx.answer(res).await
+               }
+               Command::Banlist => {
+                  let user_id = cx.update.from().unwrap().id;
+
+                  let res = match db::user_chat_name(user_id).await {
+                     Some(chat_name) => {
+                        let bans = db::bans(&chat_name).await;
+                        if bans.is_empty() {
+                           format!("В чате {} нет заблокированных авторов", chat_name)
+                        } else {
+                           // Кто именно заблокирован, не знает никто, поэтому выводим только номера и сроки
+                           bans.into_iter()
+                           .fold(format!("Заблокированные авторы чата {}:", chat_name), |acc, (id, created, until)| {
+                              format!("{}\n#{} с {} {}", acc, id, created, until.map_or(String::from("навсегда"), |until| format!("до {}", until)))
+                           }) + "\n\nДля разблокировки отправьте /unban и номер, например /unban 5"
+                        }
+                     }
+                     None => String::from(NO_CHAT),
                   };
                   cx.answer(res).await
                }
+               Command::Unban(ban_id) => {
+                  let user_id = cx.update.from().unwrap().id;
+
+                  let res = match db::user_chat_name(user_id).await {
+                     Some(chat_name) => {
+                        match ban_id.trim().trim_start_matches('#').parse::<i32>() {
+                           Ok(ban_id) => {
+                              if db::unban(&chat_name, ban_id).await {
+                                 format!("Блокировка #{} снята", ban_id)
+                              } else {
+                                 format!("Блокировки #{} в чате {} не найдено, список блокировок /banlist", ban_id, chat_name)
+                              }
+                           }
+                           Err(_) => String::from("После команды /unban надо указать номер блокировки из списка /banlist, например /unban 5"),
+                        }
+                     }
+                     None => String::from(NO_CHAT),
+                  };
+                  cx.answer(res).await
+               }
+               Command::Filter(args) => cx.answer(add_filter(cx.update.from().unwrap().id, &args).await).await,
+               Command::Filters => cx.answer(list_filters(cx.update.from().unwrap().id).await).await,
+               Command::Unfilter(args) => cx.answer(delete_filter(cx.update.from().unwrap().id, &args).await).await,
+               Command::Retract => {
+                  let (text, markup) = moderation::retract_list(cx.update.from().unwrap().id).await;
+                  match markup {
+                     Some(markup) => cx.answer(text).reply_markup(markup).send().await,
+                     None => cx.answer(text).await,
+                  }
+               }
+               Command::Settings => cx.answer(show_settings(cx.update.from().unwrap().id).await).await,
+               Command::Set(args) => cx.answer(change_setting(cx.update.from().unwrap().id, &args).await).await,
+               Command::Invite => cx.answer(create_invite(cx.update.from().unwrap().id).await).await,
+               Command::Invites => cx.answer(list_invites(cx.update.from().unwrap().id).await).await,
+               Command::Revoke(args) => cx.answer(revoke_invite(cx.update.from().unwrap().id, &args).await).await,
+               Command::Link(args) => cx.answer(chat_link(cx.update.from().unwrap().id, &args).await).await,
+               Command::Target(args) => cx.answer(change_target(cx.update.from().unwrap().id, &args).await).await,
+               Command::Poll(args) if args.trim().is_empty() => cx.answer(poll::HELP).await,
+               Command::Poll(_) => offer(&cx).await,
+               Command::Topic(args) => cx.answer(add_topic(cx.update.from().unwrap().id, &args).await).await,
+               Command::Topics => cx.answer(list_topics(cx.update.from().unwrap().id).await).await,
+               Command::Untopic(args) => cx.answer(delete_topic(cx.update.from().unwrap().id, &args).await).await,
+               Command::Modchat(args) => cx.answer(private_modchat(cx.update.from().unwrap().id, &args).await).await,
+               Command::Backups(args) => cx.answer(list_backups(cx.update.from().unwrap().id, &args).await).await,
+               Command::Queue => show_queue(&cx).await,
+               Command::Apitoken(args) => cx.answer(api_token(cx.update.from().unwrap().id, &args).await).await,
+               Command::Hook(args) => cx.answer(add_hook(cx.update.from().unwrap().id, &args).await).await,
+               Command::Hooks(args) => cx.answer(list_hooks(cx.update.from().unwrap().id, &args).await).await,
+               Command::Unhook(args) => cx.answer(delete_hook(cx.update.from().unwrap().id, &args).await).await,
             }
          } else {
-            cx.reply_to("Выберите чат для отправки")
-            .reply_markup(db::chats_markup().await)
-            .send()
-            .await
+            offer(&cx).await
+         }
+      }
+   }
+}
+
+// Предлагает выбрать чат для сообщения или, если чат выбран заранее, сразу показывает предпросмотр
+async fn offer(cx: &UpdateWithCx<AutoSend<Bot>, Message>) -> ResponseResult<Message> {
+   let chat_id = cx.update.chat_id();
+   let user_id = cx.update.from().unwrap().id;
+
+   let content = match moderation::Content::load(&cx.update).await {
+      Some(Ok(content)) => content,
+      Some(Err(e)) => return cx.answer(e).await,
+      None => return cx.answer("Текст, опрос, фото, видео, файл или аудио, пожалуйста!").await,
+   };
+
+   // Ответ на список чатов означает поиск по нему
+   let picker_id = cx.update.reply_to_message()
+   .filter(|reply| reply.from().is_some_and(|user| user.is_bot) && reply.text().is_some_and(|text| text.starts_with(picker::PROMPT)))
+   .map(|reply| reply.id);
+
+   // Если чат выбран заранее по ссылке, сообщение сразу идёт на модерацию
+   let target = match picker_id {
+      Some(_) => None,
+      None => db::target(user_id).await,
+   };
+   if let Some(target) = target {
+      if db::user_id(&target).await.is_some() {
+         let (preview, markup) = moderation::draft(&cx.requester, &target, user_id, &content).await;
+         let hint = format!("Сообщения направляются в чат {}, чтобы выбирать чат из списка, отправьте /target off", target);
+         let req = cx.reply_to(format!("{}\n\n{}", preview, html::escape(&hint)))
+         .parse_mode(ParseMode::Html);
+         return match markup {
+            Some(markup) => req.reply_markup(markup).send().await,
+            None => req.send().await,
+         };
+      }
+
+      // Чат больше не зарегистрирован
+      db::clear_target(user_id).await;
+   }
+
+   match picker_id {
+      Some(picker_id) => {
+         let picker = picker::Picker::search(cx.update.text().unwrap_or_default());
+
+         // Строка поиска больше не нужна
+         let _ = cx.requester.delete_message(chat_id, cx.update.id).send().await;
+
+         cx.requester
+         .edit_message_text(chat_id, picker_id, picker.text())
+         .reply_markup(picker.markup(user_id).await)
+         .send()
+         .await
+      }
+      None => {
+         let picker = picker::Picker::default();
+         cx.reply_to(picker.text())
+         .reply_markup(picker.markup(user_id).await)
+         .send()
+         .await
+      }
+   }
+}
+
+// Добавляет правило фильтрации по команде /filter
+async fn add_filter(user_id: i64, args: &str) -> String {
+   let chat_name = match db::user_chat_name(user_id).await {
+      Some(chat_name) => chat_name,
+      None => return String::from(NO_CHAT),
+   };
+
+   // Действие, тип и шаблон, который может содержать пробелы
+   let mut args = args.trim().splitn(3, ' ');
+   let action = args.next().and_then(|s| s.parse::<filters::Action>().ok());
+   let kind = args.next().and_then(|s| s.parse::<filters::Kind>().ok());
+   let pattern = args.next().unwrap_or_default().trim();
+
+   let (action, kind) = match (action, kind) {
+      (Some(action), Some(kind)) => (action, kind),
+      _ => return String::from("Укажите действие (reject, strip или flag) и тип правила (word, regex, links или mentions), например '/filter reject word казино' или '/filter strip links'"),
+   };
+
+   if kind.has_pattern() == pattern.is_empty() {
+      return if pattern.is_empty() {
+         format!("Для правила типа {} надо указать шаблон", kind.as_str())
+      } else {
+         format!("Правилу типа {} шаблон не нужен", kind.as_str())
+      };
+   }
+
+   // Проверим шаблон, прежде чем сохранять
+   let rule = filters::Rule { id: 0, action, kind, pattern: String::from(pattern) };
+   if let Err(e) = rule.regex() {
+      return format!("Ошибка в регулярном выражении: {}", e);
+   }
+
+   match db::add_filter(&chat_name, action, kind, pattern).await {
+      Some(id) => format!("Добавлено правило #{} для чата {}", id, chat_name),
+      None => String::from("Не удалось сохранить правило"),
+   }
+}
+
+// Выводит правила фильтрации по команде /filters
+async fn list_filters(user_id: i64) -> String {
+   match db::user_chat_name(user_id).await {
+      Some(chat_name) => {
+         let rules = db::filters(&chat_name).await;
+         if rules.is_empty() {
+            format!("Для чата {} правил фильтрации нет, добавить можно командой /filter", chat_name)
+         } else {
+            rules.into_iter()
+            .fold(format!("Правила фильтрации чата {}:", chat_name), |acc, rule| format!("{}\n{}", acc, rule))
+            + "\n\nДля удаления отправьте /unfilter и номер, например /unfilter 3"
+         }
+      }
+      None => String::from(NO_CHAT),
+   }
+}
+
+// Удаляет правило фильтрации по команде /unfilter
+async fn delete_filter(user_id: i64, args: &str) -> String {
+   match db::user_chat_name(user_id).await {
+      Some(chat_name) => {
+         match args.trim().trim_start_matches('#').parse::<i32>() {
+            Ok(id) => {
+               if db::delete_filter(&chat_name, id).await {
+                  format!("Правило #{} удалено", id)
+               } else {
+                  format!("Правила #{} в чате {} не найдено, список правил /filters", id, chat_name)
+               }
+            }
+            Err(_) => String::from("После команды /unfilter надо указать номер правила из списка /filters, например /unfilter 3"),
+         }
+      }
+      None => String::from(NO_CHAT),
+   }
+}
+
+// Проверяет, что бот может публиковать в канале, иначе возвращает пояснение
+async fn check_channel(requester: &AutoSend<Bot>, chat_id: ChatId) -> Result<(), String> {
+   let me = requester.get_me().send().await.map_err(|e| format!("Ошибка {}", e))?;
+   match requester.get_chat_member(chat_id, me.user.id).send().await {
+      Ok(member) if member.can_post_messages() => Ok(()),
+      Ok(_) => Err(String::from("Чтобы публиковать сообщения в канале, сделайте меня его администратором с правом публикации сообщений")),
+      Err(e) => Err(format!("Не удалось проверить мои права в канале, возможно вы забыли меня в него добавить: {}", e)),
+   }
+}
+
+// Назначает группу модераторов по команде /modchat, отправленной в группе
+async fn group_modchat(user_id: i64, group: &Chat) -> String {
+   let chat_name = match db::user_chat_name(user_id).await {
+      Some(chat_name) => chat_name,
+      None => return String::from("Назначить группу модераторов может только администратор чата, зарегистрированного в боте"),
+   };
+
+   // Карточки на модерацию не должны попасть на глаза участникам самого чата
+   if group.username().is_some_and(|username| chat_name.trim_start_matches('@').eq_ignore_ascii_case(username)) {
+      return String::from("Группа модераторов должна быть отдельной от самого чата");
+   }
+
+   if db::set_modchat(user_id, Some(group.id)).await {
+      format!("Сообщения в чат {} будут приходить на модерацию сюда, решение может принять любой участник группы", chat_name)
+   } else {
+      String::from("Не удалось сохранить группу модераторов")
+   }
+}
+
+// Выводит группу модераторов или возвращает модерацию в личку по команде /modchat в личке
+async fn private_modchat(user_id: i64, args: &str) -> String {
+   let chat_name = match db::user_chat_name(user_id).await {
+      Some(chat_name) => chat_name,
+      None => return String::from(NO_CHAT),
+   };
+
+   if args.trim() == "off" {
+      return if db::set_modchat(user_id, None).await {
+         format!("Сообщения в чат {} будут приходить на модерацию вам в личку", chat_name)
+      } else {
+         String::from("Не удалось изменить настройку")
+      };
+   }
+
+   match db::moderation_chat(&chat_name).await {
+      Some(mod_chat) if mod_chat != user_id => format!("Сообщения в чат {} приходят на модерацию в группу модераторов. Чтобы назначить другую, отправьте /modchat в ней, а чтобы модерировать в личке - /modchat off", chat_name),
+      _ => format!("Сообщения в чат {} приходят на модерацию вам в личку. Чтобы модерировать вместе с командой, добавьте меня в группу модераторов и отправьте в ней /modchat", chat_name),
+   }
+}
+
+// Выводит очередь ждущих решения сообщений по команде /queue
+async fn show_queue(cx: &UpdateWithCx<AutoSend<Bot>, Message>) -> ResponseResult<Message> {
+   let user_id = cx.update.from().map_or(0, |user| user.id);
+   let (text, markup) = moderation::queue(user_id, cx.update.chat_id(), 0).await;
+   let req = cx.answer(text).parse_mode(ParseMode::Html);
+   match markup {
+      Some(markup) => req.reply_markup(markup).send().await,
+      None => req.send().await,
+   }
+}
+
+// Выводит ссылку для запасных модераторов и их список по команде /backups
+async fn list_backups(user_id: i64, args: &str) -> String {
+   let chat_name = match db::user_chat_name(user_id).await {
+      Some(chat_name) => chat_name,
+      None => return String::from(NO_CHAT),
+   };
+
+   let renew = match args.trim() {
+      "" => false,
+      "new" => true,
+      _ => return String::from("Команда /backups выводит ссылку для запасных модераторов, а '/backups new' забывает их всех и заменяет ссылку"),
+   };
+
+   let link = match db::backup_link(&chat_name, renew).await {
+      Some(link) => format!("https://t.me/{}?start=mod_{}", BOT_NAME, link),
+      None => return String::from("Не удалось получить ссылку"),
+   };
+   let backups = db::backups(&chat_name).await;
+   let list = if backups.is_empty() {
+      String::from("Запасных модераторов пока нет")
+   } else {
+      backups.into_iter()
+      .fold(String::from("Запасные модераторы:"), |acc, (_, name)| format!("{}\n{}", acc, name))
+   };
+   format!("Ссылка для запасных модераторов чата {}, отправьте её тем, кто будет рассматривать сообщения, если основные модераторы не успеют:\n{}\n\n{}\n\nСроки задаются настройкой sla, подробнее в /settings", chat_name, link, list)
+}
+
+// Выдаёт или отзывает токен API по команде /apitoken
+async fn api_token(user_id: i64, args: &str) -> String {
+   match args.trim() {
+      "" => match db::issue_api_token(user_id).await {
+         Some((chat_name, token)) => format!("Токен API для чата {}, храните его в секрете, показан он только сейчас, а прежний токен больше не действует:\n{}\n\nПередавайте его в заголовке 'Authorization: Bearer токен'", chat_name, token),
+         None => String::from(NO_CHAT),
+      },
+      "off" => if db::revoke_api_token(user_id).await { String::from("Токен API отозван") } else { String::from("Действующего токена API нет") },
+      _ => String::from("Команда /apitoken выдаёт новый токен API, а '/apitoken off' отзывает его"),
+   }
+}
+
+// Добавляет адрес для уведомлений по команде /hook
+async fn add_hook(user_id: i64, args: &str) -> String {
+   let chat_name = match db::user_chat_name(user_id).await {
+      Some(chat_name) => chat_name,
+      None => return String::from(NO_CHAT),
+   };
+
+   let mut words = args.split_whitespace();
+   let url = match words.next() {
+      Some(url) => match hooks::check(url).await {
+         Ok(url) => url,
+         Err(e) => return e,
+      },
+      None => return String::from("Укажите адрес и, если нужно, события через пробел, например '/hook https://example.com/hook approved rejected'"),
+   };
+   let events = match words.map(str::parse::<hooks::Event>).collect::<Result<Vec<_>, _>>() {
+      Ok(events) if events.is_empty() => hooks::EVENTS.to_vec(),
+      Ok(events) => events,
+      Err(e) => return e,
+   };
+   let events: Vec<String> = events.iter().map(|event| String::from(event.as_str())).collect();
+
+   match db::add_hook(&chat_name, url.as_str(), &events).await {
+      Some((id, secret)) => format!("Адрес #{} будет получать уведомления чата {} о событиях: {}\n\nСекрет для проверки подписи, храните его в секрете, показан он только сейчас:\n{}\n\nЗаголовок X-Cognito-Signature содержит sha256= и HMAC-SHA256 тела запроса с этим секретом в шестнадцатеричном виде. Проверить адрес можно командой '/hooks ping {}'",
+         id, chat_name, events.join(", "), secret, id),
+      None => String::from("Не удалось сохранить адрес, попробуйте позже"),
+   }
+}
+
+// Выводит адреса для уведомлений, журнал доставки или отправляет проверочное уведомление по команде /hooks
+async fn list_hooks(user_id: i64, args: &str) -> String {
+   let chat_name = match db::user_chat_name(user_id).await {
+      Some(chat_name) => chat_name,
+      None => return String::from(NO_CHAT),
+   };
+
+   let args = args.trim();
+   if args == "log" {
+      let log = db::hook_log(&chat_name, 20).await;
+      if log.is_empty() {
+         return format!("Уведомлений для чата {} ещё не было", chat_name);
+      }
+      return log.iter()
+      .fold(format!("Последние уведомления чата {}:", chat_name), |acc, entry| format!("{}\n{}", acc, hooks::log_line(entry)));
+   }
+
+   if let Some(id) = args.strip_prefix("ping") {
+      return match id.trim().trim_start_matches('#').parse::<i32>() {
+         Ok(id) if hooks::ping(&chat_name, id).await => format!("Проверочное уведомление на адрес #{} поставлено в очередь, результат покажет '/hooks log'", id),
+         Ok(id) => format!("Адреса #{} в чате {} не найдено, список адресов /hooks", id, chat_name),
+         Err(_) => String::from("Укажите номер адреса из списка /hooks, например '/hooks ping 2'"),
+      };
+   }
+
+   let hooks = db::hooks(&chat_name).await;
+   if hooks.is_empty() {
+      format!("Для чата {} адреса для уведомлений не заданы, добавить можно командой /hook", chat_name)
+   } else {
+      hooks.into_iter()
+      .fold(format!("Адреса для уведомлений чата {}:", chat_name), |acc, (id, url, events)| format!("{}\n#{} {} ({})", acc, id, url, events.join(", ")))
+   }
+}
+
+// Удаляет адрес для уведомлений по команде /unhook
+async fn delete_hook(user_id: i64, args: &str) -> String {
+   match db::user_chat_name(user_id).await {
+      Some(chat_name) => {
+         match args.trim().trim_start_matches('#').parse::<i32>() {
+            Ok(id) => {
+               if db::delete_hook(&chat_name, id).await {
+                  format!("Адрес #{} удалён", id)
+               } else {
+                  format!("Адреса #{} в чате {} не найдено, список адресов /hooks", id, chat_name)
+               }
+            }
+            Err(_) => String::from("После команды /unhook надо указать номер адреса из списка /hooks, например /unhook 2"),
          }
       }
+      None => String::from(NO_CHAT),
+   }
+}
+
+// Добавляет тему форума для отправки по команде /topic
+async fn add_topic(user_id: i64, args: &str) -> String {
+   let chat_name = match db::user_chat_name(user_id).await {
+      Some(chat_name) => chat_name,
+      None => return String::from(NO_CHAT),
+   };
+
+   // Номер темы и название, которое может содержать пробелы
+   let (thread_id, title) = args.trim().split_once(' ').unwrap_or((args.trim(), ""));
+   let title = title.trim();
+   let thread_id = match thread_id.parse::<i32>() {
+      Ok(thread_id) if thread_id > 0 && !title.is_empty() && title.chars().count() <= 100 => thread_id,
+      _ => return String::from("Укажите номер темы и её название до 100 символов, например '/topic 12 Новости'. Номер темы - последнее число в ссылке на её первое сообщение"),
+   };
+
+   if db::add_topic(&chat_name, thread_id, title).await {
+      format!("Тема «{}» доступна для отправки в чат {}, список тем /topics", title, chat_name)
+   } else {
+      String::from("Не удалось сохранить тему, попробуйте позже")
+   }
+}
+
+// Выводит темы форума чата по команде /topics
+async fn list_topics(user_id: i64) -> String {
+   match db::user_chat_name(user_id).await {
+      Some(chat_name) => {
+         let topics = db::topics(&chat_name).await;
+         if topics.is_empty() {
+            format!("Для чата {} темы не заданы, сообщения публикуются в общий поток. Добавить тему можно командой /topic", chat_name)
+         } else {
+            topics.into_iter()
+            .fold(format!("Темы чата {}, доступные для отправки:", chat_name), |acc, (thread_id, title)| format!("{}\n{} {}", acc, thread_id, title))
+            + "\n\nДля удаления отправьте /untopic и номер, например /untopic 12"
+         }
+      }
+      None => String::from(NO_CHAT),
+   }
+}
+
+// Убирает тему форума из доступных по команде /untopic
+async fn delete_topic(user_id: i64, args: &str) -> String {
+   match db::user_chat_name(user_id).await {
+      Some(chat_name) => {
+         match args.trim().parse::<i32>() {
+            Ok(thread_id) => {
+               if db::delete_topic(&chat_name, thread_id).await {
+                  format!("Тема {} больше не доступна для отправки", thread_id)
+               } else {
+                  format!("Темы {} в чате {} не найдено, список тем /topics", thread_id, chat_name)
+               }
+            }
+            Err(_) => String::from("После команды /untopic надо указать номер темы из списка /topics, например /untopic 12"),
+         }
+      }
+      None => String::from(NO_CHAT),
+   }
+}
+
+// Выводит настройки чата по команде /settings
+async fn show_settings(user_id: i64) -> String {
+   match db::user_chat_name(user_id).await {
+      Some(chat_name) => format!("Настройки чата {}:\n{}\n\n{}", chat_name, db::settings(&chat_name).await.describe(), settings::HELP),
+      None => String::from(NO_CHAT),
+   }
+}
+
+// Изменяет настройку чата по команде /set
+async fn change_setting(user_id: i64, args: &str) -> String {
+   let chat_name = match db::user_chat_name(user_id).await {
+      Some(chat_name) => chat_name,
+      None => return String::from(NO_CHAT),
+   };
+
+   // Название и значение, которое может содержать пробелы
+   let mut args = args.trim().splitn(2, ' ');
+   let key = args.next().unwrap_or_default();
+   let value = args.next().unwrap_or_default().trim();
+   if key.is_empty() {
+      return format!("После команды /set надо указать название настройки и её значение, например '/set mode auto'\n\n{}", settings::HELP);
+   }
+
+   // Проверим значение, прежде чем сохранять
+   let mut settings = db::settings(&chat_name).await;
+   if let Err(e) = settings.set(key, value) {
+      return e;
+   }
+
+   if db::set_setting(&chat_name, key, value).await {
+      format!("Настройки чата {}:\n{}", chat_name, settings.describe())
+   } else {
+      String::from("Не удалось сохранить настройку")
+   }
+}
+
+// Пояснение для пользователя, выбравшего чат по ссылке
+fn target_hint(chat_name: &str) -> String {
+   format!("Ваши сообщения будут направляться на модерацию администратору чата {} (он не будет знать, от кого). Отправьте сообщение, а чтобы выбирать чат из списка, отправьте /target off", chat_name)
+}
+
+// Выводит или изменяет чат по умолчанию по команде /target
+async fn change_target(user_id: i64, args: &str) -> String {
+   match args.trim() {
+      "" => match db::target(user_id).await {
+         Some(target) => format!("Сообщения направляются в чат {}, чтобы выбирать чат из списка, отправьте /target off", target),
+         None => String::from("Чат по умолчанию не выбран, сообщения отправляются в выбранный из списка чат. Выбрать можно командой '/target @chat'"),
+      },
+      "off" => {
+         db::clear_target(user_id).await;
+         String::from("Чат по умолчанию забыт, теперь чат для каждого сообщения выбирается из списка")
+      }
+      chat_name => {
+         let chat_name = String::from(chat_name);
+         if db::user_id(&chat_name).await.is_none() {
+            return format!("Чат {} не зарегистрирован в боте", chat_name);
+         }
+
+         // Скрытый чат можно выбрать только по ссылке, если только он уже не выбирался
+         let hidden = db::settings(&chat_name).await.visibility != settings::Visibility::Public;
+         if hidden && db::last_chat(user_id).await.as_ref() != Some(&chat_name) {
+            return format!("Чат {} скрыт, выбрать его можно только по ссылке от администратора", chat_name);
+         }
+
+         db::set_target(user_id, &chat_name).await;
+         target_hint(&chat_name)
+      }
+   }
+}
+
+// Выводит или заменяет постоянную ссылку на чат по команде /link
+async fn chat_link(user_id: i64, args: &str) -> String {
+   let chat_name = match db::user_chat_name(user_id).await {
+      Some(chat_name) => chat_name,
+      None => return String::from(NO_CHAT),
+   };
+
+   let renew = match args.trim() {
+      "" => false,
+      "new" => true,
+      _ => return String::from("Команда /link выводит ссылку на ваш чат, а '/link new' заменяет её на новую"),
+   };
+
+   match db::chat_link(&chat_name, renew).await {
+      Some(link) => {
+         let res = format!("Ссылка для анонимной отправки сообщений в чат {}:\nhttps://t.me/{}?start={}", chat_name, BOT_NAME, link);
+         if db::settings(&chat_name).await.visibility == settings::Visibility::Invite {
+            res + "\n\nЧат принимает сообщения только по приглашению, для новых авторов создайте его командой /invite"
+         } else {
+            res
+         }
+      }
+      None => String::from("Не удалось получить ссылку"),
+   }
+}
+
+// Ссылка для приглашения в чат
+fn invite_link(token: &str) -> String {
+   format!("https://t.me/{}?start=chat_{}", BOT_NAME, token)
+}
+
+// Создаёт приглашение по команде /invite
+async fn create_invite(user_id: i64) -> String {
+   match db::user_chat_name(user_id).await {
+      Some(chat_name) => match db::new_invite(&chat_name).await {
+         Some((id, token)) => format!("Приглашение #{} в чат {}:\n{}\n\nОтозвать его можно командой /revoke {}", id, chat_name, invite_link(&token), id),
+         None => String::from("Не удалось создать приглашение"),
+      },
+      None => String::from(NO_CHAT),
+   }
+}
+
+// Выводит приглашения по команде /invites
+async fn list_invites(user_id: i64) -> String {
+   match db::user_chat_name(user_id).await {
+      Some(chat_name) => {
+         let invites = db::invites(&chat_name).await;
+         if invites.is_empty() {
+            format!("Приглашений в чат {} нет, создать можно командой /invite", chat_name)
+         } else {
+            invites.into_iter()
+            .fold(format!("Приглашения в чат {}:", chat_name), |acc, (id, token, created)| format!("{}\n#{} от {} {}", acc, id, created, invite_link(&token)))
+            + "\n\nДля отзыва отправьте /revoke и номер, например /revoke 2"
+         }
+      }
+      None => String::from(NO_CHAT),
+   }
+}
+
+// Отзывает приглашение по команде /revoke
+async fn revoke_invite(user_id: i64, args: &str) -> String {
+   match db::user_chat_name(user_id).await {
+      Some(chat_name) => {
+         match args.trim().trim_start_matches('#').parse::<i32>() {
+            Ok(id) => {
+               if db::revoke_invite(&chat_name, id).await {
+                  format!("Приглашение #{} отозвано", id)
+               } else {
+                  format!("Приглашения #{} в чат {} не найдено, список приглашений /invites", id, chat_name)
+               }
+            }
+            Err(_) => String::from("После команды /revoke надо указать номер приглашения из списка /invites, например /revoke 2"),
+         }
+      }
+      None => String::from(NO_CHAT),
    }
 }
 
@@ -120,6 +847,7 @@ async fn handle_rejection(error: warp::Rejection) -> Result<impl warp::Reply, In
    Ok(StatusCode::INTERNAL_SERVER_ERROR)
 }
 
+#[allow(clippy::extra_unused_lifetimes)]
 pub async fn webhook<'a>(bot: AutoSend<Bot>) -> impl update_listeners::UpdateListener<Infallible> {
    // Heroku defines auto defines a port value
    let teloxide_token = env::var("TELOXIDE_TOKEN").expect("TELOXIDE_TOKEN env variable missing");
@@ -140,10 +868,16 @@ pub async fn webhook<'a>(bot: AutoSend<Bot>) -> impl update_listeners::UpdateLis
 
    let (tx, rx) = mpsc::unbounded_channel();
 
-   let server = warp::post()
+   // Веб-панель администраторов обслуживается тем же сервером
+   let server = web::routes(bot.clone())
+      .or(api::routes(bot.clone()))
+      .or(metrics::route())
+      .or(warp::post()
       .and(warp::path(path))
       .and(warp::body::json())
-      .map(move |json: serde_json::Value| {
+      .map(move |mut json: serde_json::Value| {
+         metrics::UPDATES.inc();
+         formatting::prepare_update(&mut json);
          let try_parse = match serde_json::from_str(&json.to_string()) {
                Ok(update) => Ok(update),
                Err(error) => {
@@ -163,7 +897,7 @@ pub async fn webhook<'a>(bot: AutoSend<Bot>) -> impl update_listeners::UpdateLis
          }
 
          StatusCode::OK
-      })
+      }))
       .recover(handle_rejection);
 
    let (stop_token, stop_flag) = AsyncStopToken::new_pair();
@@ -193,6 +927,11 @@ async fn run() {
    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL env variable missing");
    log::info!("{}", database_url);
 
+   // Соль для хешей авторов в блокировках, при её смене все блокировки перестанут действовать
+   if env::var("HASH_SALT").unwrap_or_default().is_empty() {
+      panic!("HASH_SALT env variable missing");
+   }
+
    let connector = TlsConnector::builder()
    // .add_root_certificate(cert)
    .danger_accept_invalid_certs(true)
@@ -213,7 +952,7 @@ async fn run() {
    });
 
    // Сохраним доступ к БД
-   match db::DB.set(client) {
+   match db::DB.set(db::Client::new(client)) {
       Ok(_) => log::info!("Database connected"),
       _ => log::info!("Something wrong with database"),
    }
@@ -221,6 +960,12 @@ async fn run() {
    // Создадим таблицу в БД, если её ещё нет
    db::check_database().await;
 
+   // Доставка сообщений модераторам в назначенное время
+   tokio::spawn(moderation::scheduler(bot.clone()));
+
+   // Доставка уведомлений о событиях модерации
+   tokio::spawn(hooks::dispatcher());
+
    Dispatcher::new(bot.clone())
    .messages_handler(handle_message_query)
    .callback_queries_handler(handle_callback_query)
@@ -234,7 +979,9 @@ async fn run() {
 async fn handle_callback_query(rx: DispatcherHandlerRx<AutoSend<Bot>, CallbackQuery>) {
    UnboundedReceiverStream::new(rx)
    .for_each_concurrent(None, |cx| async move {
-      handle_callback(cx).await
+      let started = Instant::now();
+      handle_callback(cx).await;
+      metrics::CALLBACK_LATENCY.observe(started.elapsed());
     })
    .await;
 }
@@ -242,26 +989,14 @@ async fn handle_callback_query(rx: DispatcherHandlerRx<AutoSend<Bot>, CallbackQu
  async fn handle_message_query(rx: DispatcherHandlerRx<AutoSend<Bot>, Message>) {
    UnboundedReceiverStream::new(rx)
    .for_each_concurrent(None, |cx| async move {
+      let started = Instant::now();
       handle_message(cx).await.expect("Something wrong with the bot!");
+      metrics::MESSAGE_LATENCY.observe(started.elapsed());
    })
    .await;
 }
 
-// Возвращает кнопки для администратора
-fn admin_markup() -> InlineKeyboardMarkup {
-   InlineKeyboardMarkup::default()
-   .append_row(vec![InlineKeyboardButton::callback(String::from("🗸 Одобрить"), String::from("+")),
-      InlineKeyboardButton::callback(String::from("🗴 Отклонить"), String::from("-")),
-   ])
-}
-
-// Для хранения отложенного сообщения администратору чата
-struct MsgToAdmin {
-   pub id: i64,
-   pub message: String,
-   pub delay: u32,
-}
-
+#[allow(clippy::single_match)]
 async fn handle_callback(cx: UpdateWithCx<AutoSend<Bot>, CallbackQuery>) {
    let query = &cx.update;
    let query_id = &query.id;
@@ -272,40 +1007,49 @@ async fn handle_callback(cx: UpdateWithCx<AutoSend<Bot>, CallbackQuery>) {
    // Ссылка сообщение для будущей правки
    let message_id = query.message.as_ref().unwrap().id;
 
-   // Отложенное сообщение администратору чата
-   let mut msg_to_admin: Option<MsgToAdmin> = None;
-
    // Сообщение для отправки обратно
    let msg = match &query.data {
       None => {
          String::from("Error No data")
       }
       Some(data) => {
-         // Если в сообщении с кнопкой было процитированное сообщение, получим его
-         if let Some(message) = query.message.as_ref()
-         .and_then(|s| Message::reply_to_message(&s))
-         .and_then(|s| Message::text(&s)) {
-            // Код администратора по имени чата
+         // Если в сообщении с кнопкой было процитированное сообщение, получим его вместе с разметкой
+         let quoted = match query.message.as_ref().and_then(Message::reply_to_message) {
+            Some(quoted) => moderation::Content::load(quoted).await,
+            None => None,
+         };
+
+         if let Some(picker) = picker::Picker::parse(data) {
+            // Кнопки перехода по списку чатов. Ошибка означает, что список не изменился,
+            // например при нажатии на номер страницы
+            let _ = cx.requester
+            .edit_message_text(user_id, message_id, picker.text())
+            .reply_markup(picker.markup(user_id).await)
+            .send()
+            .await;
+            String::new()
+         } else if let (true, Some(content)) = (data.starts_with('@'), quoted) {
+            // Выбран чат для процитированного сообщения, проверим, что он зарегистрирован
             let admin = db::user_id(data).await;
 
             match admin {
-               Some(id) => {
-
-                  // Время задержки
-                  let delay = rand::thread_rng().gen_range(3..723);
+               Some(_) => {
+                  let (text, markup) = match content {
+                     Ok(content) => moderation::draft(&cx.requester, data, user_id, &content).await,
+                     Err(e) => (html::escape(&e), None),
+                  };
 
-                  // Приготовим для отправки сообщение администратору на модерацию
-                  msg_to_admin = Some(MsgToAdmin{
-                     id,
-                     message: String::from(message),
-                     delay,
-                  });
+                  // Запомним выбор для быстрой отправки в следующий раз
+                  db::set_last_chat(user_id, data).await;
 
-                  // Отредактируем сообщение у пользователя
-                  let res = cx.requester
-                  .edit_message_text(user_id, message_id, format!("Сообщение через {} сек. (для маскировки онлайн-активности) будет направлено на рассмотрении администратору чата и после его одобрения оно появится в чате", delay))
-                  .send().
-                  await;
+                  // Покажем у пользователя предпросмотр вместо списка
+                  let req = cx.requester
+                  .edit_message_text(user_id, message_id, text)
+                  .parse_mode(ParseMode::Html);
+                  let res = match markup {
+                     Some(markup) => req.reply_markup(markup).send().await,
+                     None => req.send().await,
+                  };
 
                   match res {
                      Ok(_) => String::from("Успешно"),
@@ -314,56 +1058,24 @@ async fn handle_callback(cx: UpdateWithCx<AutoSend<Bot>, CallbackQuery>) {
                },
                None => String::from("Error No admin")
             }
+         } else if let Some(page) = data.strip_prefix('q').and_then(|page| page.parse::<i64>().ok()) {
+            // Переход по очереди модератора, в группе модераторов сообщение с очередью в ней самой
+            let chat_id = query.message.as_ref().unwrap().chat.id;
+            let (text, markup) = moderation::queue(user_id, chat_id, page).await;
+            let req = cx.requester
+            .edit_message_text(chat_id, message_id, text)
+            .parse_mode(ParseMode::Html);
+            let _ = match markup {
+               Some(markup) => req.reply_markup(markup).send().await,
+               None => req.send().await,
+            };
+            String::new()
+         } else if data.starts_with(|c| "rsectn".contains(c)) {
+            // Кнопки автора под его сообщением
+            moderation::handle_author_callback(&cx, data).await
          } else {
-            // Возможно это было сообщение от админа
-            match data.as_str() {
-               "+" => {
-                  // Отправим сообщение в чат
-                  if let Some(message) = query.message.as_ref()
-                  .and_then(|s| Message::text(&s)) {
-                     // Отредактируем сообщение у администратора, ошибку игнорируем
-                     let _= cx.requester
-                     .edit_message_text(user_id, message_id, format!("Одобрено:\n{}", message))
-                     .send()
-                     .await;
-
-                     // Получим имя чата по коду пользователя
-                     let chat_name = db::user_chat_name(user_id).await.unwrap_or_default();
-
-                     // Код чата
-                     let chat_id = ChatId::ChannelUsername(chat_name);
-                     
-                     // Отправляем сообщение
-                     let res = cx.requester
-                     .send_message(chat_id, message)
-                     .send()
-                     .await;
-
-                     match res {
-                        Ok(_) => {
-                           db::successful_sent(user_id).await;
-                           String::from("Одобрено")
-                        },
-                        Err(e) => {
-                           db::error_happened(user_id).await;
-                           format!("Ошибка {}", e)
-                        },
-                     }
-                  } else {String::from("Ошибка, нет сообщения")}
-               },
-               "-" => {
-                  // Отредактируем сообщение у администратора, ошибку игнорируем
-                  if let Some(message) = query.message.as_ref()
-                  .and_then(|s| Message::text(&s)) {
-                     let _= cx.requester
-                     .edit_message_text(user_id, message_id, format!("Отклонено:\n{}", message))
-                     .send()
-                     .await;
-                  }
-                  String::from("Отклонено")
-               },
-               _ => String::from("Слишком старое сообщение"),
-            }
+            // Сообщение от админа
+            moderation::handle_admin_callback(&cx, data).await
          }
       }
    };
@@ -376,22 +1088,4 @@ async fn handle_callback(cx: UpdateWithCx<AutoSend<Bot>, CallbackQuery>) {
          Err(_) => log::info!("Error handle_message {}", &msg),
          _ => (),
    }
-
-   // Если приготовлено отложенное сообщение, надо отправить его
-   if let Some(msg) = msg_to_admin {
-      // Выжидаем паузу
-      sleep(Duration::from_secs(u64::from(msg.delay))).await;
-
-      // Отправляем сообщение админу
-      let res = cx.requester
-      .send_message(ChatId::Id(msg.id), msg.message)
-      .reply_markup(admin_markup())
-      .send()
-      .await;
-
-      // Фиксируем ошибку, если была, при этом не фиксируем успешную отправку, чтобы не обнулить счётчик отправок в чат
-      if res.is_err() {
-         db::error_happened(msg.id).await;
-      }
-   }
 }
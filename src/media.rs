@@ -0,0 +1,255 @@
+/* ===============================================================================
+Бот для анонимизации сообщений для чата.
+Сообщения с фото, видео и файлами, в том числе альбомы. 19 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use once_cell::sync::Lazy;
+use std::{collections::HashSet, fmt, sync::Mutex};
+use teloxide::{
+   prelude::*,
+   types::{ChatId, InlineKeyboardMarkup, InputFile, InputMedia, InputMediaPhoto, InputMediaVideo, InputMediaDocument, InputMediaAudio, ParseMode,},
+   requests::ResponseResult,
+};
+use tokio::time::{sleep, Duration};
+use crate::database as db;
+use crate::topics;
+
+// Части альбома приходят отдельными сообщениями почти одновременно, столько ждём остальные
+const ALBUM_WAIT: Duration = Duration::from_secs(2);
+
+// Альбомы, части которых ещё собираются
+static COLLECTING: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Вид вложения
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+   Photo,
+   Video,
+   Document,
+   Audio,
+}
+
+impl Kind {
+   fn as_str(&self) -> &'static str {
+      match self {
+         Kind::Photo => "photo",
+         Kind::Video => "video",
+         Kind::Document => "document",
+         Kind::Audio => "audio",
+      }
+   }
+}
+
+/// Вложение, хранится только код файла на серверах Telegram
+#[derive(Clone)]
+pub struct Media {
+   pub kind: Kind,
+   pub file_id: String,
+   // Имя файла и название трека для файлов и аудио, они публикуются вместе с ними
+   pub name: Option<String>,
+}
+
+impl fmt::Display for Media {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      match &self.name {
+         Some(name) => write!(f, "{}:{}:{}", self.kind.as_str(), self.file_id, name),
+         None => write!(f, "{}:{}", self.kind.as_str(), self.file_id),
+      }
+   }
+}
+
+impl Media {
+   /// Вложение сообщения, если есть
+   pub fn from_message(message: &Message) -> Option<Self> {
+      let (kind, file_id, name) = if let Some(photo) = message.photo() {
+         // Самый большой из размеров фото идёт последним
+         (Kind::Photo, photo.last()?.file_id.clone(), None)
+      } else if let Some(video) = message.video() {
+         (Kind::Video, video.file_id.clone(), None)
+      } else if let Some(document) = message.document() {
+         (Kind::Document, document.file_id.clone(), document.file_name.clone())
+      } else if let Some(audio) = message.audio() {
+         let title = [&audio.performer, &audio.title, &audio.file_name].iter()
+         .filter_map(|part| part.as_deref())
+         .collect::<Vec<_>>()
+         .join(" - ");
+         (Kind::Audio, audio.file_id.clone(), Some(title).filter(|title| !title.is_empty()))
+      } else {
+         return None;
+      };
+      Some(Self { kind, file_id, name })
+   }
+
+   /// Разбирает сохранённое в виде "photo:код" или "document:код:имя"
+   pub fn parse(s: &str) -> Option<Self> {
+      let mut parts = s.splitn(3, ':');
+      let (kind, file_id, name) = (parts.next()?, parts.next()?, parts.next());
+      let kind = match kind {
+         "photo" => Kind::Photo,
+         "video" => Kind::Video,
+         "document" => Kind::Document,
+         "audio" => Kind::Audio,
+         _ => return None,
+      };
+      Some(Self { kind, file_id: String::from(file_id), name: name.map(String::from) })
+   }
+
+   // Для отправки альбомом, подпись в HTML
+   fn input(&self, caption: Option<&str>) -> InputMedia {
+      let file = InputFile::FileId(self.file_id.clone());
+      macro_rules! with_caption {
+         ($media:expr) => {
+            match caption {
+               Some(caption) => $media.caption(caption).parse_mode(ParseMode::Html),
+               None => $media,
+            }
+         };
+      }
+      match self.kind {
+         Kind::Photo => InputMedia::Photo(with_caption!(InputMediaPhoto::new(file))),
+         Kind::Video => InputMedia::Video(with_caption!(InputMediaVideo::new(file))),
+         Kind::Document => InputMedia::Document(with_caption!(InputMediaDocument::new(file))),
+         Kind::Audio => InputMedia::Audio(with_caption!(InputMediaAudio::new(file))),
+      }
+   }
+}
+
+/// Описание вложений для предпросмотра и модератора, например "📎 2 фото, видео"
+pub fn describe(media: &[Media]) -> String {
+   let names = [(Kind::Photo, "фото"), (Kind::Video, "видео"), (Kind::Document, "файл"), (Kind::Audio, "аудио")];
+   let parts: Vec<String> = names.iter()
+   .filter_map(|(kind, name)| {
+      match media.iter().filter(|m| m.kind == *kind).count() {
+         0 => None,
+         1 => Some(String::from(*name)),
+         count => Some(format!("{} {}", count, name)),
+      }
+   })
+   .collect();
+   format!("📎 {}", parts.join(", "))
+}
+
+/// Предупреждение автору о файлах и аудио: Telegram публикует их как есть, с именем
+/// и встроенными метаданными, которые бот удалить не может
+pub fn metadata_warning(media: &[Media]) -> Option<String> {
+   let files: Vec<&Media> = media.iter().filter(|m| matches!(m.kind, Kind::Document | Kind::Audio)).collect();
+   if files.is_empty() {
+      return None;
+   }
+   let names: Vec<String> = files.iter().map(|m| format!("«{}»", m.name.as_deref().unwrap_or("без имени"))).collect();
+   Some(format!("📄 Файлы публикуются как есть: {}. Имя файла и метаданные внутри него (автор документа, программа, исполнитель, место съёмки) могут указать на вас, при необходимости переименуйте файл и очистите метаданные перед отправкой", names.join(", ")))
+}
+
+/// Как публиковать сообщение в чате
+#[derive(Default)]
+pub struct Placement {
+   // Сообщение чата, на которое это отвечает
+   pub reply_to: Option<i32>,
+   // Тема форума
+   pub thread: Option<i32>,
+   // Без звукового уведомления
+   pub silent: bool,
+   // Кнопки под сообщением, к альбому их не прикрепить
+   pub markup: Option<InlineKeyboardMarkup>,
+}
+
+/// Применяет к запросу отправки ответ на сообщение и отключение звука из Placement.
+/// Ответ отправится, даже если исходное сообщение успели удалить
+macro_rules! place {
+   ($req:expr, $placement:expr) => {{
+      let placement: &$crate::media::Placement = $placement;
+      let req = $req.disable_notification(placement.silent);
+      match placement.reply_to {
+         Some(reply_to) => req.reply_to_message_id(reply_to).allow_sending_without_reply(true),
+         None => req,
+      }
+   }};
+}
+pub(crate) use place;
+
+/// Отправляет вложения с подписью в HTML, несколько - одним альбомом
+pub async fn send(requester: &AutoSend<Bot>, chat_id: ChatId, media: &[Media], caption: &str, placement: &Placement) -> ResponseResult<Vec<Message>> {
+   let caption = if caption.is_empty() { None } else { Some(caption) };
+
+   if let [single] = media {
+      let file = InputFile::FileId(single.file_id.clone());
+      macro_rules! with_markup {
+         ($req:expr) => {
+            match &placement.markup {
+               Some(markup) => place!($req, placement).reply_markup(markup.clone()),
+               None => place!($req, placement),
+            }
+         };
+      }
+      macro_rules! send_single {
+         ($req:expr) => {
+            match caption {
+               Some(caption) => topics::send(requester, with_markup!($req).caption(caption).parse_mode(ParseMode::Html), placement.thread).await,
+               None => topics::send(requester, with_markup!($req), placement.thread).await,
+            }
+         };
+      }
+      let res = match single.kind {
+         Kind::Photo => send_single!(requester.send_photo(chat_id, file)),
+         Kind::Video => send_single!(requester.send_video(chat_id, file)),
+         Kind::Document => send_single!(requester.send_document(chat_id, file)),
+         Kind::Audio => send_single!(requester.send_audio(chat_id, file)),
+      };
+      return res.map(|message| vec![message]);
+   }
+
+   // Подпись альбома показывается у первого вложения
+   let inputs = media.iter().enumerate()
+   .map(|(i, m)| m.input(if i == 0 { caption } else { None }));
+   topics::send(requester, place!(requester.send_media_group(chat_id, inputs), placement), placement.thread).await
+}
+
+/// Сохраняет часть альбома. Обработать альбом целиком должен только обработчик первой
+/// пришедшей части, для неё возвращается true, когда остальные части уже собраны
+pub async fn collect(message: &Message) -> bool {
+   let group = match message.media_group_id() {
+      Some(group) => String::from(group),
+      None => return false,
+   };
+   let media = match Media::from_message(message) {
+      Some(media) => media,
+      None => return false,
+   };
+
+   let entities = serde_json::to_string(message.caption_entities().unwrap_or_default()).unwrap_or_default();
+   db::add_album_part(&group, message.id, &media.to_string(), message.caption().unwrap_or_default(), &entities).await;
+
+   if !COLLECTING.lock().unwrap().insert(group.clone()) {
+      return false;
+   }
+   sleep(ALBUM_WAIT).await;
+   COLLECTING.lock().unwrap().remove(&group);
+   true
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn stored_form_round_trip() {
+      for s in ["photo:AgAD", "document:BQAC:отчёт: итоги.pdf", "audio:CQAC:Исполнитель - Песня"] {
+         assert_eq!(Media::parse(s).unwrap().to_string(), s);
+      }
+      assert!(Media::parse("sticker:CAAC").is_none());
+      assert!(Media::parse("photo").is_none());
+   }
+
+   #[test]
+   fn warning_only_for_files() {
+      let photo = Media::parse("photo:AgAD").unwrap();
+      let document = Media::parse("document:BQAC:отчёт.pdf").unwrap();
+      assert_eq!(metadata_warning(std::slice::from_ref(&photo)), None);
+      let warning = metadata_warning(&[photo, document]).unwrap();
+      assert!(warning.starts_with("📄") && warning.contains("«отчёт.pdf»"));
+   }
+}
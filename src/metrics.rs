@@ -0,0 +1,210 @@
+/* ===============================================================================
+Бот для анонимизации сообщений для чата.
+Метрики для Prometheus. 19 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use std::{env, fmt::Write, sync::atomic::{AtomicU64, Ordering}, time::Duration};
+use warp::{http::{header, StatusCode}, reply::Response, Filter, Rejection, Reply};
+
+use crate::quorum::Verdict;
+
+/// Счётчик
+pub struct Counter(AtomicU64);
+
+impl Counter {
+   const fn new() -> Self {
+      Self(AtomicU64::new(0))
+   }
+
+   pub fn inc(&self) {
+      self.0.fetch_add(1, Ordering::Relaxed);
+   }
+
+   fn get(&self) -> u64 {
+      self.0.load(Ordering::Relaxed)
+   }
+}
+
+// Границы корзин гистограмм в секундах
+const BUCKETS: [f64; 12] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Гистограмма длительностей
+pub struct Histogram {
+   // Число наблюдений не длиннее границы каждой корзины
+   buckets: [AtomicU64; BUCKETS.len()],
+   count: AtomicU64,
+   // Сумма в микросекундах
+   sum: AtomicU64,
+}
+
+impl Histogram {
+   const fn new() -> Self {
+      #[allow(clippy::declare_interior_mutable_const)]
+      const ZERO: AtomicU64 = AtomicU64::new(0);
+      Self { buckets: [ZERO; BUCKETS.len()], count: ZERO, sum: ZERO }
+   }
+
+   pub fn observe(&self, duration: Duration) {
+      let seconds = duration.as_secs_f64();
+      for (bucket, bound) in self.buckets.iter().zip(BUCKETS.iter()) {
+         if seconds <= *bound {
+            bucket.fetch_add(1, Ordering::Relaxed);
+         }
+      }
+      self.count.fetch_add(1, Ordering::Relaxed);
+      self.sum.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+   }
+
+   // Строки гистограммы, labels - метки без фигурных скобок
+   fn render(&self, out: &mut String, name: &str, labels: &str) {
+      let sep = if labels.is_empty() { "" } else { "," };
+      for (bucket, bound) in self.buckets.iter().zip(BUCKETS.iter()) {
+         let _ = writeln!(out, "{}_bucket{{{}{}le=\"{}\"}} {}", name, labels, sep, bound, bucket.load(Ordering::Relaxed));
+      }
+      let count = self.count.load(Ordering::Relaxed);
+      let braces = if labels.is_empty() { String::new() } else { format!("{{{}}}", labels) };
+      let _ = writeln!(out, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, labels, sep, count);
+      let _ = writeln!(out, "{}_sum{} {}", name, braces, self.sum.load(Ordering::Relaxed) as f64 / 1e6);
+      let _ = writeln!(out, "{}_count{} {}", name, braces, count);
+   }
+}
+
+/// Обновления, пришедшие от Telegram
+pub static UPDATES: Counter = Counter::new();
+
+/// Сообщения, отправку которых подтвердили авторы
+pub static SUBMISSIONS: Counter = Counter::new();
+
+// Решения модераторов и решения по умолчанию
+static APPROVED: Counter = Counter::new();
+static REJECTED: Counter = Counter::new();
+static EXPIRED: Counter = Counter::new();
+
+/// Неудачные публикации в чатах
+pub static PUBLISH_FAILURES: Counter = Counter::new();
+
+/// Ошибки отправки в чаты, учтённые счётчиком ошибок
+pub static ERRORS: Counter = Counter::new();
+
+/// Чаты, забытые из-за повторяющихся ошибок
+pub static UNREGISTRATIONS: Counter = Counter::new();
+
+/// Время обработки сообщений
+pub static MESSAGE_LATENCY: Histogram = Histogram::new();
+
+/// Время обработки нажатий на кнопки
+pub static CALLBACK_LATENCY: Histogram = Histogram::new();
+
+/// Время запросов к БД
+pub static DB_QUERY: Histogram = Histogram::new();
+
+/// Учитывает решение по сообщению
+pub fn decision(verdict: Verdict) {
+   match verdict {
+      Verdict::Approve => APPROVED.inc(),
+      Verdict::Reject => REJECTED.inc(),
+      Verdict::Expire => EXPIRED.inc(),
+   }
+}
+
+/// Все метрики в текстовом формате Prometheus
+pub fn render() -> String {
+   let mut out = String::new();
+   let counters: [(&str, &str, &Counter); 5] = [
+      ("cognito_updates_total", "Updates received from Telegram", &UPDATES),
+      ("cognito_submissions_total", "Submissions confirmed by authors", &SUBMISSIONS),
+      ("cognito_publish_failures_total", "Failed attempts to publish in a chat", &PUBLISH_FAILURES),
+      ("cognito_errors_total", "Invocations of error_happened", &ERRORS),
+      ("cognito_unregistrations_total", "Chats unregistered automatically after repeated errors", &UNREGISTRATIONS),
+   ];
+   for (name, help, counter) in counters.iter() {
+      let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter\n{} {}", name, help, name, name, counter.get());
+   }
+
+   let _ = writeln!(out, "# HELP cognito_decisions_total Moderation decisions by verdict\n# TYPE cognito_decisions_total counter");
+   for (verdict, counter) in [(Verdict::Approve, &APPROVED), (Verdict::Reject, &REJECTED), (Verdict::Expire, &EXPIRED)].iter() {
+      let _ = writeln!(out, "cognito_decisions_total{{verdict=\"{}\"}} {}", verdict.as_str(), counter.get());
+   }
+
+   let _ = writeln!(out, "# HELP cognito_handler_duration_seconds Time spent handling an update\n# TYPE cognito_handler_duration_seconds histogram");
+   MESSAGE_LATENCY.render(&mut out, "cognito_handler_duration_seconds", "handler=\"message\"");
+   CALLBACK_LATENCY.render(&mut out, "cognito_handler_duration_seconds", "handler=\"callback\"");
+
+   let _ = writeln!(out, "# HELP cognito_db_query_duration_seconds Time spent in database queries\n# TYPE cognito_db_query_duration_seconds histogram");
+   DB_QUERY.render(&mut out, "cognito_db_query_duration_seconds", "");
+   out
+}
+
+/// Маршрут /metrics. Если задана переменная окружения METRICS_TOKEN, нужен заголовок
+/// "Authorization: Bearer токен"
+pub fn route() -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+   warp::get()
+   .and(warp::path!("metrics"))
+   .and(warp::header::optional::<String>("authorization"))
+   .map(|auth: Option<String>| {
+      if let Ok(token) = env::var("METRICS_TOKEN") {
+         if auth.as_deref() != Some(&format!("Bearer {}", token)) {
+            return StatusCode::UNAUTHORIZED.into_response();
+         }
+      }
+      warp::reply::with_header(render(), header::CONTENT_TYPE, "text/plain; version=0.0.4").into_response()
+   })
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn histogram_buckets() {
+      let histogram = Histogram::new();
+      histogram.observe(Duration::from_millis(3));
+      histogram.observe(Duration::from_millis(200));
+      histogram.observe(Duration::from_secs(20));
+
+      let mut out = String::new();
+      histogram.render(&mut out, "test_seconds", "handler=\"message\"");
+      let lines: Vec<&str> = out.lines().collect();
+      assert_eq!(lines.len(), BUCKETS.len() + 3);
+      assert_eq!(lines[0], "test_seconds_bucket{handler=\"message\",le=\"0.001\"} 0");
+      assert_eq!(lines[1], "test_seconds_bucket{handler=\"message\",le=\"0.005\"} 1");
+      assert_eq!(lines[5], "test_seconds_bucket{handler=\"message\",le=\"0.1\"} 1");
+      assert_eq!(lines[6], "test_seconds_bucket{handler=\"message\",le=\"0.25\"} 2");
+      assert_eq!(lines[11], "test_seconds_bucket{handler=\"message\",le=\"10\"} 2");
+      assert_eq!(lines[12], "test_seconds_bucket{handler=\"message\",le=\"+Inf\"} 3");
+      assert_eq!(lines[13], "test_seconds_sum{handler=\"message\"} 20.203");
+      assert_eq!(lines[14], "test_seconds_count{handler=\"message\"} 3");
+
+      // Без меток фигурные скобки остаются только у корзин
+      let mut out = String::new();
+      histogram.render(&mut out, "test_seconds", "");
+      assert!(out.starts_with("test_seconds_bucket{le=\"0.001\"} 0\n"));
+      assert!(out.ends_with("test_seconds_sum 20.203\ntest_seconds_count 3\n"));
+   }
+
+   #[test]
+   fn exposition_format() {
+      let count = |out: &str| out.lines()
+      .find_map(|line| line.strip_prefix("cognito_decisions_total{verdict=\"reject\"} "))
+      .and_then(|value| value.parse::<u64>().ok());
+      let before = count(&render()).unwrap();
+      decision(Verdict::Reject);
+      let out = render();
+      assert_eq!(count(&out), Some(before + 1));
+
+      assert!(out.contains("# HELP cognito_updates_total Updates received from Telegram\n# TYPE cognito_updates_total counter\ncognito_updates_total "));
+      assert!(out.contains("# TYPE cognito_handler_duration_seconds histogram\ncognito_handler_duration_seconds_bucket{handler=\"message\",le=\"0.001\"} "));
+      assert!(out.contains("cognito_db_query_duration_seconds_bucket{le=\"+Inf\"} "));
+
+      // Кроме комментариев, каждая строка - имя с необязательными метками и число
+      for line in out.lines().filter(|line| !line.starts_with('#')) {
+         let (name, value) = line.rsplit_once(' ').unwrap();
+         assert!(value.parse::<f64>().is_ok(), "{}", line);
+         assert!(name.starts_with("cognito_") && !name.contains(' '), "{}", line);
+      }
+   }
+}
@@ -0,0 +1,1098 @@
+/* ===============================================================================
+Бот для анонимизации сообщений для чата.
+Модерация сообщений. 19 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use teloxide::{
+   prelude::*,
+   types::{ChatId, InlineKeyboardMarkup, InlineKeyboardButton, CallbackQuery, MessageEntity, ParseMode,},
+   requests::ResponseResult,
+   utils::html,
+};
+use tokio::time::{sleep, Duration};
+use rand::seq::SliceRandom;
+
+use crate::database as db;
+use crate::filters;
+use crate::hooks::{self, Event};
+use crate::media::{self, place, Media, Placement};
+use crate::metrics;
+use crate::poll::Poll;
+use crate::quorum::{Quorum, Verdict};
+use crate::reply;
+use crate::sanitize;
+use crate::settings::{Mode, Retract, Settings, Visibility};
+use crate::topics;
+
+// Возвращает кнопки для модераторов, при голосовании на них видно, сколько голосов набрано из нужных
+fn admin_markup(id: i32, quorum: &Quorum, (approvals, rejections): (i64, i64)) -> InlineKeyboardMarkup {
+   let caption = |text: &str, count: i64, need: u32| if quorum.is_vote() { format!("{} {}/{}", text, count, need) } else { String::from(text) };
+   InlineKeyboardMarkup::default()
+   .append_row(vec![InlineKeyboardButton::callback(caption("🗸 Одобрить", approvals, quorum.approvals), format!("+{}", id)),
+      InlineKeyboardButton::callback(caption("🗴 Отклонить", rejections, quorum.rejections), format!("-{}", id)),
+   ])
+   .append_row(vec![InlineKeyboardButton::callback(String::from("⛔ Заблокировать автора"), format!("b{}", id))])
+}
+
+// Возвращает кнопки для администратора под уже опубликованным сообщением
+fn post_markup(id: i32) -> InlineKeyboardMarkup {
+   InlineKeyboardMarkup::default()
+   .append_row(vec![InlineKeyboardButton::callback(String::from("🗑 Удалить из чата"), format!("d{}", id))])
+   .append_row(vec![InlineKeyboardButton::callback(String::from("⛔ Заблокировать автора"), format!("b{}", id))])
+}
+
+// Возвращает кнопки выбора срока блокировки автора
+fn ban_markup(id: i32) -> InlineKeyboardMarkup {
+   InlineKeyboardMarkup::default()
+   .append_row(vec![InlineKeyboardButton::callback(String::from("День"), format!("b{}:1", id)),
+      InlineKeyboardButton::callback(String::from("Неделя"), format!("b{}:7", id)),
+      InlineKeyboardButton::callback(String::from("Месяц"), format!("b{}:30", id)),
+      InlineKeyboardButton::callback(String::from("Навсегда"), format!("b{}:0", id)),
+   ])
+   .append_row(vec![InlineKeyboardButton::callback(String::from("« Назад"), format!("a{}", id))])
+}
+
+/// Содержимое сообщения автора
+pub enum Content {
+   Text(String, Vec<MessageEntity>),
+   Poll(Poll),
+   // Вложения с подписью и её разметкой
+   Media(Vec<Media>, String, Vec<MessageEntity>),
+}
+
+impl Content {
+   /// Содержимое сообщения: присланный опрос, опрос из команды /poll, вложения, для части
+   /// альбома весь альбом, или текст с разметкой
+   pub async fn load(message: &Message) -> Option<Result<Self, String>> {
+      if let Some(poll) = message.poll() {
+         return Some(Ok(Content::Poll(Poll::from_telegram(poll))));
+      }
+
+      if let Some(group) = message.media_group_id() {
+         let (list, caption, entities) = db::album(group).await;
+         if list.is_empty() {
+            return Some(Err(String::from("Альбом устарел, отправьте его заново")));
+         }
+         let entities = serde_json::from_str(&entities).unwrap_or_default();
+         return Some(Ok(Content::Media(list, caption, entities)));
+      }
+
+      if let Some(single) = Media::from_message(message) {
+         let caption = String::from(message.caption().unwrap_or_default());
+         let entities = message.caption_entities().unwrap_or_default().to_vec();
+         return Some(Ok(Content::Media(vec![single], caption, entities)));
+      }
+
+      let text = message.text()?;
+      if text.starts_with("/poll") {
+         Some(Poll::parse_command(text).map(Content::Poll))
+      } else {
+         Some(Ok(Content::Text(String::from(text), message.entities().unwrap_or_default().to_vec())))
+      }
+   }
+}
+
+// Добавляет к кнопкам публикации кнопки автора под предпросмотром сообщения. Если в чате есть
+// темы форума, отправить можно только после выбора темы
+fn draft_markup(markup: InlineKeyboardMarkup, id: i32, topics: &[(i32, String)], thread: Option<i32>, silent: bool) -> InlineKeyboardMarkup {
+   let markup = topics.iter()
+   .fold(markup, |acc, (thread_id, title)| {
+      let caption = if thread == Some(*thread_id) { format!("✓ {}", title) } else { format!("🗂 {}", title) };
+      acc.append_row(vec![InlineKeyboardButton::callback(caption, format!("t{}:{}", id, thread_id))])
+   });
+   let markup = if topics.is_empty() || topics.iter().any(|(thread_id, _)| thread == Some(*thread_id)) {
+      markup.append_row(vec![InlineKeyboardButton::callback(String::from("✅ Отправить"), format!("s{}", id))])
+   } else {
+      markup
+   };
+   let sound = if silent { "🔕 Без звука" } else { "🔔 Со звуком" };
+   markup.append_row(vec![InlineKeyboardButton::callback(String::from(sound), format!("n{}", id))])
+   .append_row(vec![InlineKeyboardButton::callback(String::from("✏ Изменить"), format!("e{}", id)),
+      InlineKeyboardButton::callback(String::from("✖ Отмена"), format!("c{}", id)),
+   ])
+}
+
+// Возвращает кнопку отмены ещё не доставленного сообщения
+fn cancel_markup(id: i32) -> InlineKeyboardMarkup {
+   InlineKeyboardMarkup::default()
+   .append_row(vec![InlineKeyboardButton::callback(String::from("✖ Отменить отправку"), format!("c{}", id))])
+}
+
+/// Готовит черновик сообщения автора для выбранного чата и возвращает предпросмотр в HTML
+/// с кнопками подтверждения или, если отправка невозможна, пояснение без кнопок
+pub async fn draft(requester: &AutoSend<Bot>, chat_name: &str, author_id: i64, content: &Content) -> (String, Option<InlineKeyboardMarkup>) {
+   match prepare(chat_name, author_id, content).await {
+      Ok(submission) => {
+         let (text, markup) = preview(requester, &submission).await;
+         (text, Some(markup))
+      }
+      Err(e) => (html::escape(&e), None),
+   }
+}
+
+// Предпросмотр черновика в HTML таким, каким его опубликует publish: с подписью чата и кнопкой
+// обсуждения, а под ними кнопки автора
+async fn preview(requester: &AutoSend<Bot>, submission: &db::Submission) -> (String, InlineKeyboardMarkup) {
+   let settings = db::settings(&submission.chat_name).await;
+   let (text, placement) = publication(requester, submission, &settings).await;
+   let body = match (&submission.poll, &submission.media) {
+      (Some(poll), _) => poll.html(),
+      (None, Some(list)) if text.is_empty() => media::describe(list),
+      (None, Some(list)) => format!("{}\n{}", media::describe(list), text),
+      (None, None) => text,
+   };
+
+   // Тему и исправления при очистке автор тоже должен видеть, остальные пометки только для модератора
+   let changes: Vec<&str> = submission.notes.lines().filter(|note| ["🗂", "↩", "🧹", "✂", "📄"].iter().any(|mark| note.starts_with(mark))).collect();
+   let changes = if changes.is_empty() { String::new() } else { format!("\n\n{}", html::escape(&changes.join("\n"))) };
+   let topics = db::topics(&submission.chat_name).await;
+   let hint = if topics.is_empty() || submission.thread.is_some() { "" } else { "\n\nВыберите тему, в которой оно появится" };
+   let text = format!("Так сообщение будет выглядеть в чате {}:\n\n{}{}{}", html::escape(&submission.chat_name), body, changes, hint);
+   (text, draft_markup(placement.markup.unwrap_or_default(), submission.id, &topics, submission.thread, placement.silent))
+}
+
+// Проверяет, очищает и сохраняет сообщение как черновик, при ошибке возвращает пояснение для автора
+async fn prepare(chat_name: &str, author_id: i64, content: &Content) -> Result<db::Submission, String> {
+   // Заблокированному автору откажем, не сообщая об этом администратору
+   if let Some(until) = db::ban_until(chat_name, &db::author_hash(chat_name, author_id)).await {
+      return Err(match until {
+         Some(until) => format!("Отправка сообщений в чат {} для вас заблокирована до {}", chat_name, until),
+         None => format!("Отправка сообщений в чат {} для вас заблокирована", chat_name),
+      });
+   }
+
+   // В закрытый чат могут писать только приглашённые и сам администратор
+   let settings = db::settings(chat_name).await;
+   if settings.visibility == Visibility::Invite
+   && db::user_id(&String::from(chat_name)).await != Some(author_id)
+   && !db::has_access(chat_name, author_id).await {
+      return Err(format!("Чат {} принимает сообщения только по приглашению его администратора", chat_name));
+   }
+
+   let rules = db::filters(chat_name).await;
+   let (reply, mut checked) = match content {
+      Content::Text(text, entities) => {
+         let (reply, text, entities) = reply::split(text, entities);
+         (reply, check_text(&rules, chat_name, &text, &entities)?)
+      }
+      Content::Poll(poll) => (None, check_poll(&rules, chat_name, poll)?),
+      Content::Media(list, caption, entities) => {
+         let (reply, caption, entities) = reply::split(caption, entities);
+         (reply, check_media(&rules, chat_name, list, &caption, &entities)?)
+      }
+   };
+
+   // Ответить можно только на сообщение того же чата
+   if let Some(reply) = reply {
+      if !reply.chat_name.eq_ignore_ascii_case(chat_name) {
+         return Err(format!("Ссылка в первой строке ведёт на сообщение другого чата, а ответить можно только на сообщение чата {}", chat_name));
+      }
+      checked.notes.insert(0, format!("↩ Ответ на сообщение {}", reply.link()));
+      checked.draft.reply_to = Some(reply.message_id);
+   }
+
+   let mode = effective_mode(settings.mode, checked.flagged);
+
+   let id = db::new_submission(chat_name, author_id, &checked.draft, &checked.notes.join("\n"), mode.as_str()).await;
+   match id {
+      Some(id) => db::submission(id).await.ok_or_else(|| String::from("Не удалось сохранить сообщение, попробуйте позже")),
+      None => Err(String::from("Не удалось сохранить сообщение, попробуйте позже")),
+   }
+}
+
+// Очищенное и проверенное фильтрами сообщение
+struct Checked {
+   draft: db::Draft,
+   // Исправления и сработавшие правила для модератора
+   notes: Vec<String>,
+   // Сработало ли хоть одно правило пометки
+   flagged: bool,
+}
+
+// Очищает текст и проверяет его правилами фильтрации чата
+fn check_text(rules: &[filters::Rule], chat_name: &str, text: &str, entities: &[MessageEntity]) -> Result<Checked, String> {
+   // Уберём из текста то, по чему можно опознать автора
+   let (cleaned, html) = sanitize::clean_formatted(text, entities);
+
+   // Проверим сообщение правилами фильтрации чата
+   let verdict = filters::apply(rules, &cleaned.text);
+   if verdict.rejected.is_some() {
+      return Err(format!("Сообщение отклонено фильтром чата {}", chat_name));
+   }
+   if verdict.text.is_empty() {
+      return Err(format!("После применения фильтров чата {} от сообщения ничего не осталось", chat_name));
+   }
+   let flagged = !verdict.notes.is_empty();
+
+   // Исправления и сработавшие правила показываются модератору перед текстом сообщения
+   let mut notes: Vec<String> = cleaned.changes.into_iter()
+   .map(|change| format!("🧹 При очистке {}", change))
+   .chain(verdict.notes)
+   .collect();
+
+   // Разметку сохраним, если она была и фильтры ничего не вырезали из текста, иначе она не будет ему соответствовать
+   let html = if entities.is_empty() {
+      None
+   } else if verdict.text == cleaned.text {
+      Some(html)
+   } else {
+      notes.push(String::from("✂ Форматирование снято из-за вырезанного текста"));
+      None
+   };
+
+   Ok(Checked { draft: db::Draft { text: verdict.text, html, poll: None, media: None, reply_to: None }, notes, flagged })
+}
+
+// Очищает вопрос и варианты ответа опроса и проверяет их правилами фильтрации чата
+fn check_poll(rules: &[filters::Rule], chat_name: &str, poll: &Poll) -> Result<Checked, String> {
+   let mut notes = Vec::new();
+   let mut flagged = false;
+
+   let mut parts = std::iter::once(&poll.question).chain(&poll.options).map(|part| {
+      let (cleaned, _) = sanitize::clean_formatted(part, &[]);
+      let verdict = filters::apply(rules, &cleaned.text);
+      if verdict.rejected.is_some() {
+         return Err(format!("Опрос отклонён фильтром чата {}", chat_name));
+      }
+      if verdict.text.is_empty() {
+         return Err(format!("После применения фильтров чата {} от вопроса или варианта ответа ничего не осталось", chat_name));
+      }
+      flagged |= !verdict.notes.is_empty();
+
+      // Одинаковые пометки для разных частей перечислим один раз
+      for note in cleaned.changes.into_iter().map(|change| format!("🧹 При очистке {}", change)).chain(verdict.notes) {
+         if !notes.contains(&note) {
+            notes.push(note);
+         }
+      }
+      Ok(verdict.text)
+   }).collect::<Result<Vec<_>, _>>()?.into_iter();
+
+   let question = parts.next().unwrap_or_default();
+   let poll = Poll { question: question.clone(), options: parts.collect(), ..poll.clone() };
+   poll.check()?;
+
+   Ok(Checked { draft: db::Draft { text: question, html: None, poll: Some(poll), media: None, reply_to: None }, notes, flagged })
+}
+
+// Очищает подпись к вложениям и проверяет её правилами фильтрации чата, подписи может и не быть
+fn check_media(rules: &[filters::Rule], chat_name: &str, list: &[Media], caption: &str, entities: &[MessageEntity]) -> Result<Checked, String> {
+   let mut checked = if caption.is_empty() {
+      Checked { draft: db::Draft { text: String::new(), html: None, poll: None, media: None, reply_to: None }, notes: Vec::new(), flagged: false }
+   } else {
+      check_text(rules, chat_name, caption, entities)?
+   };
+   checked.notes.extend(media::metadata_warning(list));
+   checked.draft.media = Some(list.to_vec());
+   Ok(checked)
+}
+
+// Ставит подтверждённый автором черновик в очередь и возвращает ответ для автора
+async fn confirm(submission: &db::Submission) -> Result<String, String> {
+   // Время доставки отсчитывается от подтверждения
+   let timing = db::settings(&submission.chat_name).await.timing;
+   let delay = timing.delay();
+   let when = timing.describe(delay);
+
+   // Тему могли убрать из разрешённых, пока автор решал
+   let topics = db::topics(&submission.chat_name).await;
+   if !topics.is_empty() && !topics.iter().any(|(thread_id, _)| submission.thread == Some(*thread_id)) {
+      return Err(String::from("Выбранная тема больше недоступна, отправьте сообщение заново"));
+   }
+
+   if !db::confirm(submission.id, delay).await {
+      return Err(String::from("Сообщение уже отправлено или отменено"));
+   }
+   metrics::SUBMISSIONS.inc();
+   hooks::fire(Event::Received, submission, None).await;
+
+   Ok(match submission.mode.parse().unwrap_or(Mode::Manual) {
+      Mode::Manual | Mode::Filter => format!("Сообщение {} (для маскировки онлайн-активности) будет направлено на рассмотрении администратору чата и после его одобрения оно появится в чате. До этого отправку можно отменить", when),
+      Mode::Auto => format!("Сообщение {} (для маскировки онлайн-активности) будет опубликовано в чате. До этого отправку можно отменить", when),
+      Mode::Post => format!("Сообщение {} (для маскировки онлайн-активности) будет опубликовано в чате, администратор сможет удалить его позже. До публикации отправку можно отменить", when),
+   })
+}
+
+/// Доставляет сообщения из очереди, когда подходит их время
+pub async fn scheduler(requester: AutoSend<Bot>) {
+   loop {
+      sleep(Duration::from_secs(5)).await;
+
+      // Забытые черновики и авторы сообщений, по которым всё решено
+      db::drop_drafts().await;
+      db::forget_authors().await;
+
+      // Сроки рассмотрения и голосования
+      review_timeouts(&requester).await;
+
+      // Выпустим набравшиеся пакеты
+      for (chat_name, count, age) in db::waiting_batches().await {
+         if db::settings(&chat_name).await.timing.batch_ready(count, age) {
+            db::release_batch(&chat_name).await;
+         }
+      }
+
+      // Доставляем в случайном порядке, чтобы порядок в пакете ничего не говорил о времени отправки
+      let mut due = db::take_due().await;
+      due.shuffle(&mut rand::thread_rng());
+      for submission in due {
+         deliver(&requester, submission).await;
+      }
+   }
+}
+
+/// Текст для модератора в HTML: пометки и само сообщение
+pub fn card_text(submission: &db::Submission) -> String {
+   if submission.notes.is_empty() {
+      submission.html()
+   } else {
+      format!("{}\n\n{}", html::escape(&submission.notes), submission.html())
+   }
+}
+
+// Отправляет сообщение в HTML: заголовок и текст сообщения с разметкой
+fn send_card(requester: &AutoSend<Bot>, chat_id: i64, header: &str, submission: &db::Submission) -> <AutoSend<Bot> as Requester>::SendMessage {
+   requester
+   .send_message(chat_id, format!("{}:\n{}", html::escape(header), submission.html()))
+   .parse_mode(ParseMode::Html)
+}
+
+// Исправляет сообщение у модератора: заголовок и текст сообщения с разметкой
+fn edit_card(requester: &AutoSend<Bot>, chat_id: i64, message_id: i32, header: &str, submission: &db::Submission) -> <AutoSend<Bot> as Requester>::EditMessageText {
+   requester
+   .edit_message_text(chat_id, message_id, format!("{}:\n{}", html::escape(header), submission.html()))
+   .parse_mode(ParseMode::Html)
+}
+
+// Режим, в котором сохраняется сообщение: в режиме filter сообщение, на которое сработали
+// правила, требует модерации, а остальные публикуются сразу
+fn effective_mode(mode: Mode, flagged: bool) -> Mode {
+   match mode {
+      Mode::Filter if flagged => Mode::Manual,
+      Mode::Filter => Mode::Auto,
+      mode => mode,
+   }
+}
+
+// Что получат модераторы о сообщении, опубликованном без модерации
+#[derive(Debug, PartialEq)]
+enum Report {
+   // Ничего, в режиме auto администратору сообщается только об ошибке
+   Nothing,
+   // Карточку с кнопкой удаления из чата, в режиме post
+   Card(String),
+   // Сообщение о неудачной публикации
+   Failure(String),
+}
+
+// Отчёт модераторам по результату публикации, card - текст карточки сообщения
+fn report(mode: Mode, res: Result<(), String>, card: &str) -> Report {
+   match res {
+      Ok(_) if mode == Mode::Auto => Report::Nothing,
+      Ok(_) => Report::Card(format!("Опубликовано:\n{}", card)),
+      Err(e) => Report::Failure(format!("Не удалось опубликовать сообщение ({}):\n{}", html::escape(&e), card)),
+   }
+}
+
+/// Отправляет сообщение администратору или, если модерация не требуется, сразу в чат
+async fn deliver(requester: &AutoSend<Bot>, submission: db::Submission) {
+   // Автор мог отозвать сообщение, пока оно ждало в очереди
+   if submission.status != "pending" {
+      return;
+   }
+
+   // Чат могли забыть, пока сообщение ждало
+   let admin_id = match db::user_id(&submission.chat_name).await {
+      Some(admin_id) => admin_id,
+      None => return,
+   };
+
+   // На модерацию сообщение идёт в группу модераторов, если она задана
+   let mod_chat = db::moderation_chat(&submission.chat_name).await.unwrap_or(admin_id);
+
+   let mode = submission.mode.parse().unwrap_or(Mode::Manual);
+   let (text, markup) = match mode {
+      Mode::Manual | Mode::Filter => {
+         let quorum = db::settings(&submission.chat_name).await.quorum;
+         (card_text(&submission), admin_markup(submission.id, &quorum, (0, 0)))
+      }
+      Mode::Auto | Mode::Post => {
+         if !db::decide(submission.id, "approved").await {
+            return;
+         }
+         metrics::decision(Verdict::Approve);
+         hooks::fire(Event::Approved, &submission, None).await;
+
+         // Неудачная публикация учитывается в метриках самой publish
+         let res = publish(requester, admin_id, &submission).await
+         .map(|_| ())
+         .map_err(|e| e.to_string());
+
+         match report(mode, res, &card_text(&submission)) {
+            Report::Nothing => return,
+            Report::Card(text) => (text, post_markup(submission.id)),
+            Report::Failure(text) => {
+               let _ = requester
+               .send_message(ChatId::Id(mod_chat), text)
+               .parse_mode(ParseMode::Html)
+               .send()
+               .await;
+               return;
+            }
+         }
+      }
+   };
+
+   // Для ответа модератору нужно видеть, на что отвечают, поэтому перешлём ему исходное сообщение
+   if let (Mode::Manual | Mode::Filter, Some(reply_to)) = (mode, submission.reply_to) {
+      let _ = requester
+      .forward_message(ChatId::Id(mod_chat), ChatId::ChannelUsername(submission.chat_name.clone()), reply_to)
+      .send()
+      .await;
+   }
+
+   // Вложения модератор должен увидеть до решения, кнопки к ним не прикрепить, поэтому они идут отдельно
+   if let (Mode::Manual | Mode::Filter, Some(list)) = (mode, &submission.media) {
+      let _ = media::send(requester, ChatId::Id(mod_chat), list, &submission.text_html(), &Placement::default()).await;
+   }
+
+   // Отправляем сообщение на модерацию
+   let res = requester
+   .send_message(ChatId::Id(mod_chat), text)
+   .parse_mode(ParseMode::Html)
+   .reply_markup(markup)
+   .send()
+   .await;
+
+   // Фиксируем ошибку, если была, при этом не фиксируем успешную отправку, чтобы не обнулить счётчик отправок в чат
+   match res {
+      Ok(card) => db::set_card(submission.id, mod_chat, card.id).await,
+      Err(_) => db::error_happened(admin_id).await,
+   }
+}
+
+/// Публикует сообщение в чате и запоминает его код, чтобы сообщение можно было потом удалить
+pub async fn publish(requester: &AutoSend<Bot>, admin_id: i64, submission: &db::Submission) -> ResponseResult<Vec<Message>> {
+   let chat_id = ChatId::ChannelUsername(submission.chat_name.clone());
+   let settings = db::settings(&submission.chat_name).await;
+   let (text, placement) = publication(requester, submission, &settings).await;
+
+   let res = match (&submission.poll, &submission.media) {
+      // Опрос всегда анонимный, чтобы не было видно и проголосовавших
+      (Some(poll), _) => {
+         let req = requester
+         .send_poll(chat_id, poll.question.clone(), poll.options.clone(), poll.poll_type())
+         .is_anonymous(true)
+         .allows_multiple_answers(poll.multiple);
+         let req = place!(req, &placement);
+         let req = match poll.correct {
+            Some(correct) => req.correct_option_id(correct as u8),
+            None => req,
+         };
+         let req = match &placement.markup {
+            Some(markup) => req.reply_markup(markup.clone()),
+            None => req,
+         };
+         topics::send(requester, req, placement.thread).await.map(|message| vec![message])
+      }
+      (None, Some(list)) => media::send(requester, chat_id, list, &text, &placement).await,
+      (None, None) => {
+         let req = requester
+         .send_message(chat_id, text)
+         .parse_mode(ParseMode::Html);
+         let req = place!(req, &placement);
+         let req = match &placement.markup {
+            Some(markup) => req.reply_markup(markup.clone()),
+            None => req,
+         };
+         topics::send(requester, req, placement.thread).await.map(|message| vec![message])
+      }
+   };
+
+   match &res {
+      Ok(messages) => {
+         let ids: Vec<i32> = messages.iter().map(|message| message.id).collect();
+         db::published(submission.id, &ids).await;
+         db::successful_sent(admin_id).await;
+         hooks::fire(Event::Published, submission, None).await;
+      }
+      Err(e) => {
+         metrics::PUBLISH_FAILURES.inc();
+         db::error_happened(admin_id).await;
+         hooks::fire(Event::PublishFailed, submission, Some(&e.to_string())).await;
+      }
+   }
+   res
+}
+
+// Текст публикации в HTML с подписью чата и то, как её публиковать. У опроса подпись
+// некуда поставить, поэтому текст для него не используется
+async fn publication(requester: &AutoSend<Bot>, submission: &db::Submission, settings: &Settings) -> (String, Placement) {
+   let placement = Placement {
+      reply_to: submission.reply_to,
+      thread: submission.thread,
+      silent: submission.silent.unwrap_or(settings.silent),
+      markup: if settings.comments { comments_markup(requester, &submission.chat_name).await } else { None },
+   };
+
+   // Подпись чата идёт после текста
+   let signature = html::escape(&settings.signature(crate::BOT_NAME, &submission.chat_name));
+   let text = match (submission.text.is_empty(), signature.is_empty()) {
+      (_, true) => submission.text_html(),
+      (true, false) => signature,
+      (false, false) => format!("{}\n\n{}", submission.text_html(), signature),
+   };
+   (text, placement)
+}
+
+// Кнопка перехода в группу обсуждения канала, если она к нему привязана
+async fn comments_markup(requester: &AutoSend<Bot>, chat_name: &str) -> Option<InlineKeyboardMarkup> {
+   let chat = requester.get_chat(ChatId::ChannelUsername(String::from(chat_name))).send().await.ok()?;
+   if !chat.is_channel() {
+      return None;
+   }
+
+   // У закрытой группы нет имени, тогда нужна ссылка-приглашение, которую бот видит как её администратор
+   let group = requester.get_chat(chat.linked_chat_id()?).send().await.ok()?;
+   let url = match group.username() {
+      Some(username) => format!("https://t.me/{}", username),
+      None => String::from(group.invite_link()?),
+   };
+   Some(InlineKeyboardMarkup::default()
+   .append_row(vec![InlineKeyboardButton::url(String::from("💬 Обсудить"), url)]))
+}
+
+/// Учитывает голос модератора и, если голосов достаточно, принимает решение. card - карточка,
+/// на которой проголосовали, by - кто и когда. Возвращает результат для модератора
+pub async fn cast_vote(requester: &AutoSend<Bot>, admin_id: i64, card: (i64, i32), submission: &db::Submission, voter_id: i64, approve: bool, by: String) -> String {
+   if submission.status != "pending" {
+      return String::from("Решение уже принято");
+   }
+
+   // Без голосования решает первый нажавший
+   let quorum = db::settings(&submission.chat_name).await.quorum;
+   let votes = db::vote(submission.id, voter_id, approve).await;
+   match quorum.outcome(votes.0, votes.1) {
+      Some(verdict) => {
+         let note = if quorum.is_vote() { format!("за {}, против {}; {}", votes.0, votes.1, by) } else { by };
+         resolve(requester, admin_id, card, submission, verdict, &note).await
+         .unwrap_or_else(|| String::from("Решение уже принято"))
+      }
+      None => {
+         let _= requester
+         .edit_message_reply_markup(card.0, card.1)
+         .reply_markup(admin_markup(submission.id, &quorum, votes))
+         .send()
+         .await;
+         format!("Голос учтён: за {}, против {}", votes.0, votes.1)
+      }
+   }
+}
+
+// Применяет решение по ожидающему модерации сообщению и исправляет карточку, на которой нажата
+// кнопка, и основную карточку у модераторов, note - кто и как решил. Возвращает результат для модератора
+// или None, если решение уже было принято
+async fn resolve(requester: &AutoSend<Bot>, admin_id: i64, card: (i64, i32), submission: &db::Submission, verdict: Verdict, note: &str) -> Option<String> {
+   let status = match verdict {
+      Verdict::Approve => "approved",
+      Verdict::Reject => "rejected",
+      Verdict::Expire => "expired",
+   };
+   if !db::decide(submission.id, status).await {
+      return None;
+   }
+   metrics::decision(verdict);
+   let event = if verdict == Verdict::Approve { Event::Approved } else { Event::Rejected };
+   hooks::fire(event, submission, None).await;
+
+   // Опубликованное сообщение модераторы смогут удалить позже
+   let (header, markup, res) = match verdict {
+      Verdict::Approve => match publish(requester, admin_id, submission).await {
+         Ok(_) => (format!("Одобрено ({})", note), Some(post_markup(submission.id)), String::from("Одобрено")),
+         Err(e) => (format!("Одобрено ({}), но опубликовать не удалось ({})", note, e), None, format!("Ошибка {}", e)),
+      },
+      Verdict::Reject => (format!("Отклонено ({})", note), None, String::from("Отклонено")),
+      Verdict::Expire => (format!("Снято с рассмотрения ({})", note), None, String::from("Снято с рассмотрения")),
+   };
+
+   // Запасному модератору сообщение могло прийти отдельно, тогда исправим обе карточки, ошибки игнорируем
+   let main = submission.card_chat.zip(submission.card_id);
+   let cards = std::iter::once(card).chain(main.filter(|main| *main != card));
+   for (chat_id, message_id) in cards {
+      let req = edit_card(requester, chat_id, message_id, &header, submission);
+      let _= match &markup {
+         Some(markup) => req.reply_markup(markup.clone()).send().await,
+         None => req.send().await,
+      };
+   }
+   Some(res)
+}
+
+// Следит за сроками рассмотрения: напоминает модераторам, передаёт сообщение запасным модераторам
+// и принимает решение по умолчанию, если модераторы не успели
+async fn review_timeouts(requester: &AutoSend<Bot>) {
+   for db::Awaiting { submission, age, admin_id, settings, votes } in db::awaiting_review().await {
+      let card = match submission.card_id {
+         Some(card_id) => (submission.card_chat.unwrap_or(admin_id), card_id),
+         None => continue,
+      };
+      let passed = |limit: Option<u32>| limit.is_some_and(|limit| age >= i64::from(limit));
+
+      if let Some((timeout, verdict)) = settings.sla.expire.filter(|(timeout, _)| passed(Some(*timeout))) {
+         let (approvals, rejections) = votes;
+         let note = format!("за {}, против {}; решение принято автоматически через {} сек.", approvals, rejections, timeout);
+         if resolve(requester, admin_id, card, &submission, verdict, &note).await.is_some() {
+            notify_author(requester, &submission, verdict).await;
+         }
+      } else if passed(settings.sla.escalate) && db::mark_escalated(submission.id).await {
+         escalate(requester, admin_id, card, &submission, age, &settings.quorum, votes).await;
+      } else if passed(settings.sla.remind) && db::mark_reminded(submission.id).await {
+         let _= requester
+         .send_message(card.0, format!("⏰ Сообщение ждёт решения уже {}", describe_age(age)))
+         .reply_to_message_id(card.1)
+         .allow_sending_without_reply(true)
+         .send()
+         .await;
+      }
+   }
+}
+
+// Передаёт не рассмотренное вовремя сообщение запасным модераторам, а если их нет
+// и модерация идёт в группе, то администратору чата
+async fn escalate(requester: &AutoSend<Bot>, admin_id: i64, card: (i64, i32), submission: &db::Submission, age: i64, quorum: &Quorum, votes: (i64, i64)) {
+   let mut recipients: Vec<i64> = db::backups(&submission.chat_name).await.into_iter().map(|(user_id, _)| user_id).collect();
+   if recipients.is_empty() && card.0 != admin_id {
+      recipients.push(admin_id);
+   }
+
+   let header = format!("⚠ Модераторы не рассмотрели сообщение за {}", describe_age(age));
+   for user_id in recipients {
+      let _= send_card(requester, user_id, &header, submission)
+      .reply_markup(admin_markup(submission.id, quorum, votes))
+      .send()
+      .await;
+   }
+}
+
+// Сообщает автору о решении, принятом без модераторов
+async fn notify_author(requester: &AutoSend<Bot>, submission: &db::Submission, verdict: Verdict) {
+   let begin: String = submission.text.chars().take(30).collect();
+   let res = match verdict {
+      Verdict::Approve => "модераторы не успели его рассмотреть, поэтому оно опубликовано автоматически",
+      Verdict::Reject => "модераторы не успели его рассмотреть, поэтому оно отклонено",
+      Verdict::Expire => "истёк срок рассмотрения, сообщение не будет опубликовано",
+   };
+   let author_id = match submission.author_id {
+      Some(author_id) => author_id,
+      None => return,
+   };
+   let _= requester
+   .send_message(author_id, format!("Ваше сообщение в чат {} «{}»: {}", submission.chat_name, begin, res))
+   .send()
+   .await;
+}
+
+/// Удаляет опубликованное сообщение из чата, при ошибке возвращает пояснение
+async fn unpublish(requester: &AutoSend<Bot>, submission: &db::Submission) -> Result<(), String> {
+   let published_ids = match db::unpublish(submission.id).await {
+      Some(published_ids) => published_ids,
+      None => return Err(String::from("Сообщение не опубликовано или уже удалено")),
+   };
+
+   // Альбом удаляется по одному сообщению
+   for published_id in published_ids {
+      requester
+      .delete_message(ChatId::ChannelUsername(submission.chat_name.clone()), published_id)
+      .send()
+      .await
+      .map_err(|e| format!("Ошибка {}", e))?;
+   }
+   Ok(())
+}
+
+/// Возвращает список сообщений автора, которые он может отозвать, с кнопками для отзыва
+pub async fn retract_list(author_id: i64) -> (String, Option<InlineKeyboardMarkup>) {
+   let submissions = db::author_submissions(author_id).await;
+   if submissions.is_empty() {
+      return (String::from("Нет сообщений, которые можно отозвать"), None);
+   }
+
+   // На кнопке название чата и начало сообщения
+   let markup = submissions.into_iter()
+   .fold(InlineKeyboardMarkup::default(), |acc, submission| {
+      let text: String = submission.text.chars().take(30).collect();
+      let icon = if submission.status == "pending" { "⏳" } else { "🗑" };
+      acc.append_row(vec![InlineKeyboardButton::callback(format!("{} {}: {}", icon, submission.chat_name, text), format!("r{}", submission.id))])
+   });
+
+   (String::from("Выберите сообщение, которое хотите отозвать. Ожидающее модерации (⏳) не будет опубликовано, а опубликованное (🗑) будет удалено из чата"), Some(markup))
+}
+
+/// Обрабатывает кнопки автора: выбор темы, публикацию без звука, подтверждение, исправление
+/// и отмену черновика, отмену отправки и отзыв
+pub async fn handle_author_callback(cx: &UpdateWithCx<AutoSend<Bot>, CallbackQuery>, data: &str) -> String {
+   let query = &cx.update;
+   let user_id = query.from.id;
+   let message_id = query.message.as_ref().unwrap().id;
+
+   // Номер сообщения и для темы её номер через двоеточие
+   let mut params = data.get(1..).unwrap_or_default().splitn(2, ':');
+   let submission = match params.next().and_then(|s| s.parse::<i32>().ok()) {
+      Some(id) => db::submission(id).await,
+      None => return String::from("Слишком старое сообщение"),
+   };
+   let submission = match submission {
+      Some(submission) if submission.author_id == Some(user_id) => submission,
+      _ => return String::from("Сообщение не найдено"),
+   };
+
+   if data.starts_with('t') {
+      return choose_topic(cx, &submission, params.next().and_then(|s| s.parse::<i32>().ok())).await;
+   }
+   if data.starts_with('n') {
+      return toggle_silent(cx, &submission).await;
+   }
+
+   let (res, markup) = match data.chars().next() {
+      Some('s') => match confirm(&submission).await {
+         Ok(res) => (res, Some(cancel_markup(submission.id))),
+         Err(e) => (e, None),
+      },
+      Some('e') => {
+         db::cancel(submission.id).await;
+         (format!("Отправьте исправленное сообщение и снова выберите чат {}. Прежний текст:\n{}", submission.chat_name, submission.text), None)
+      }
+      Some('c') => {
+         if db::cancel(submission.id).await {
+            (String::from("Отправка отменена"), None)
+         } else {
+            (String::from("Сообщение уже доставлено, отозвать его можно командой /retract"), None)
+         }
+      }
+      _ => (retract(cx, &submission).await, None),
+   };
+
+   // Заменим кнопки результатом
+   let req = cx.requester.edit_message_text(user_id, message_id, res.clone());
+   let _= match markup {
+      Some(markup) => req.reply_markup(markup).send().await,
+      None => req.send().await,
+   };
+
+   // Во всплывающем окошке достаточно начала
+   res.chars().take(200).collect()
+}
+
+// Запоминает выбранную автором тему и обновляет предпросмотр, возвращает результат для него
+async fn choose_topic(cx: &UpdateWithCx<AutoSend<Bot>, CallbackQuery>, submission: &db::Submission, thread: Option<i32>) -> String {
+   let title = match set_topic(submission, thread).await {
+      Ok(title) => title,
+      Err(e) => return e,
+   };
+
+   refresh_preview(cx, submission.id).await;
+   format!("Тема «{}»", title)
+}
+
+// Переключает публикацию черновика без звука и обновляет предпросмотр
+async fn toggle_silent(cx: &UpdateWithCx<AutoSend<Bot>, CallbackQuery>, submission: &db::Submission) -> String {
+   let silent = !submission.silent.unwrap_or(db::settings(&submission.chat_name).await.silent);
+   if !db::set_silent(submission.id, silent).await {
+      return String::from("Сообщение уже отправлено или отменено");
+   }
+
+   refresh_preview(cx, submission.id).await;
+   String::from(if silent { "Сообщение будет опубликовано без звука" } else { "Сообщение будет опубликовано со звуком" })
+}
+
+// Показывает заново предпросмотр черновика после изменения
+async fn refresh_preview(cx: &UpdateWithCx<AutoSend<Bot>, CallbackQuery>, id: i32) {
+   if let Some(submission) = db::submission(id).await {
+      let (text, markup) = preview(&cx.requester, &submission).await;
+      let _ = cx.requester
+      .edit_message_text(cx.update.from.id, cx.update.message.as_ref().unwrap().id, text)
+      .parse_mode(ParseMode::Html)
+      .reply_markup(markup)
+      .send()
+      .await;
+   }
+}
+
+// Запоминает тему черновика и возвращает её название или пояснение, почему выбрать её нельзя
+async fn set_topic(submission: &db::Submission, thread: Option<i32>) -> Result<String, String> {
+   let topics = db::topics(&submission.chat_name).await;
+   let (thread_id, title) = match topics.into_iter().find(|(thread_id, _)| thread == Some(*thread_id)) {
+      Some(topic) => topic,
+      None => return Err(String::from("Эта тема больше недоступна")),
+   };
+
+   // Тема показывается модератору первой пометкой
+   let notes: Vec<String> = std::iter::once(format!("🗂 Тема «{}»", title))
+   .chain(submission.notes.lines().filter(|note| !note.starts_with("🗂")).map(String::from))
+   .collect();
+   if !db::set_thread(submission.id, thread_id, &notes.join("\n")).await {
+      return Err(String::from("Сообщение уже отправлено или отменено"));
+   }
+   Ok(title)
+}
+
+/// Принимает сообщение без предпросмотра, например от интеграции через API: проверяет и очищает
+/// его как обычно, выбирает тему и сразу ставит в очередь. Возвращает номер сообщения и ответ для автора
+pub async fn submit(chat_name: &str, author_id: i64, content: &Content, thread: Option<i32>) -> Result<(i32, String), String> {
+   let mut submission = prepare(chat_name, author_id, content).await?;
+   if thread.is_some() {
+      set_topic(&submission, thread).await?;
+      submission = db::submission(submission.id).await.ok_or_else(|| String::from("Не удалось сохранить сообщение, попробуйте позже"))?;
+   }
+   let res = confirm(&submission).await?;
+   Ok((submission.id, res))
+}
+
+// Отзывает сообщение автора и возвращает результат для него
+async fn retract(cx: &UpdateWithCx<AutoSend<Bot>, CallbackQuery>, submission: &db::Submission) -> String {
+   let mod_chat = db::moderation_chat(&submission.chat_name).await;
+
+   if db::decide(submission.id, "retracted").await {
+      // Сообщение ещё не дошло до чата, исправим сообщение у модераторов, если оно уже отправлено
+      if let (Some(card_chat), Some(card_id)) = (submission.card_chat.or(mod_chat), submission.card_id) {
+         let _= edit_card(&cx.requester, card_chat, card_id, "Отозвано автором", submission)
+         .send()
+         .await;
+      }
+      String::from("Сообщение отозвано и не будет опубликовано")
+   } else if submission.status == "approved" && submission.published_id.is_some() {
+      match db::settings(&submission.chat_name).await.retract {
+         Retract::Direct => {
+            match unpublish(&cx.requester, submission).await {
+               Ok(_) => {
+                  if let Some(mod_chat) = mod_chat {
+                     let _= send_card(&cx.requester, mod_chat, "Автор удалил из чата своё сообщение", submission)
+                     .send()
+                     .await;
+                  }
+                  String::from("Сообщение удалено из чата")
+               }
+               Err(e) => e,
+            }
+         }
+         Retract::Ask => {
+            let markup = InlineKeyboardMarkup::default()
+            .append_row(vec![InlineKeyboardButton::callback(String::from("🗑 Удалить из чата"), format!("d{}", submission.id)),
+               InlineKeyboardButton::callback(String::from("Оставить"), format!("k{}", submission.id)),
+            ]);
+            let res = match mod_chat {
+               Some(mod_chat) => send_card(&cx.requester, mod_chat, "Автор просит удалить из чата своё сообщение", submission)
+                  .reply_markup(markup)
+                  .send()
+                  .await
+                  .is_ok(),
+               None => false,
+            };
+            if res {
+               String::from("Администратору чата отправлена просьба удалить сообщение")
+            } else {
+               String::from("Не удалось связаться с администратором чата")
+            }
+         }
+      }
+   } else {
+      String::from("Сообщение уже нельзя отозвать")
+   }
+}
+
+/// Очередь ждущих решения сообщений чатов модератора: по одному сообщению на странице с кнопками
+/// решения и перехода. Возвращает текст в HTML и кнопки
+pub async fn queue(user_id: i64, chat_id: i64, page: i64) -> (String, Option<InlineKeyboardMarkup>) {
+   let chats = db::moderated_chats(user_id, chat_id).await;
+   if chats.is_empty() {
+      return (String::from("Вы не модерируете ни одного чата"), None);
+   }
+
+   let counts = db::queue_counts(&chats).await;
+   let total: i64 = counts.iter().map(|(_, count)| count).sum();
+   if total == 0 {
+      return (String::from("Сообщений, ждущих решения, нет"), None);
+   }
+
+   // Очередь могла сократиться, пока модератор листал
+   let page = page.clamp(0, total - 1);
+   let (submission, age) = match db::queue(&chats, page, 1).await.into_iter().next() {
+      Some(item) => item,
+      None => return (String::from("Сообщений, ждущих решения, нет"), None),
+   };
+
+   let counts = counts.iter().map(|(chat_name, count)| format!("{} - {}", chat_name, count)).collect::<Vec<_>>().join(", ");
+   let text = format!("Ждут решения: {}\n\n{}, ждёт {}:\n{}", html::escape(&counts), html::escape(&submission.chat_name), describe_age(age), card_text(&submission));
+
+   let quorum = db::settings(&submission.chat_name).await.quorum;
+   let mut markup = admin_markup(submission.id, &quorum, db::votes(submission.id).await);
+   if total > 1 {
+      let mut row = Vec::new();
+      if page > 0 {
+         row.push(InlineKeyboardButton::callback(String::from("◀"), format!("q{}", page - 1)));
+      }
+      row.push(InlineKeyboardButton::callback(format!("{}/{}", page + 1, total), format!("q{}", page)));
+      if page + 1 < total {
+         row.push(InlineKeyboardButton::callback(String::from("▶"), format!("q{}", page + 1)));
+      }
+      markup = markup.append_row(row);
+   }
+   (text, Some(markup))
+}
+
+// Сколько времени ждёт сообщение, для модератора
+fn describe_age(age: i64) -> String {
+   match age / 60 {
+      minutes if minutes < 60 => format!("{} мин.", minutes),
+      minutes if minutes < 24 * 60 => format!("{} ч. {} мин.", minutes / 60, minutes % 60),
+      minutes => format!("{} дн. {} ч.", minutes / (24 * 60), minutes / 60 % 24),
+   }
+}
+
+/// Обрабатывает кнопки под сообщением, направленным на модерацию администратору или в группу модераторов.
+/// Данные кнопки - действие одним символом, номер сообщения и необязательный параметр через двоеточие
+pub async fn handle_admin_callback(cx: &UpdateWithCx<AutoSend<Bot>, CallbackQuery>, data: &str) -> String {
+   let query = &cx.update;
+   let user_id = query.from.id;
+   let message_id = query.message.as_ref().unwrap().id;
+   let chat_id = query.message.as_ref().unwrap().chat.id;
+
+   // Разберём данные кнопки
+   let action = data.get(0..1).unwrap_or_default();
+   let mut params = data.get(1..).unwrap_or_default().splitn(2, ':');
+   let submission = match params.next().and_then(|s| s.parse::<i32>().ok()) {
+      Some(id) => db::submission(id).await,
+      None => return String::from("Слишком старое сообщение"),
+   };
+   let submission = match submission {
+      Some(submission) => submission,
+      None => return String::from("Сообщение не найдено"),
+   };
+
+   // Решение может принимать администратор того чата, куда направлено сообщение, любой участник его группы модераторов
+   // или запасной модератор
+   let admin_id = match db::user_id(&submission.chat_name).await {
+      Some(admin_id) => admin_id,
+      None => return String::from("Чат больше не зарегистрирован"),
+   };
+   if user_id != admin_id && db::moderation_chat(&submission.chat_name).await != Some(chat_id)
+   && !db::backups(&submission.chat_name).await.iter().any(|(backup_id, _)| *backup_id == user_id) {
+      return String::from("Вы не модератор этого чата");
+   }
+
+   // В карточке видно, кто и когда принял решение
+   let by = format!("{}, {}", query.from.mention().unwrap_or_else(|| query.from.full_name()), chrono::Utc::now().format("%d.%m.%Y %H:%M UTC"));
+   let signed = |header: &str| format!("{} ({})", header, by);
+
+   match (action, params.next()) {
+      ("+", None) | ("-", None) => {
+         cast_vote(&cx.requester, admin_id, (chat_id, message_id), &submission, user_id, action == "+", by).await
+      },
+      ("d", None) => {
+         match unpublish(&cx.requester, &submission).await {
+            Ok(_) => {
+               let _= edit_card(&cx.requester, chat_id, message_id, &signed("Удалено из чата"), &submission)
+               .send()
+               .await;
+               String::from("Удалено")
+            }
+            Err(e) => e,
+         }
+      },
+      ("k", None) => {
+         // Администратор решил оставить сообщение, которое автор просил удалить
+         let _= edit_card(&cx.requester, chat_id, message_id, &signed("Оставлено в чате"), &submission)
+         .send()
+         .await;
+         String::from("Оставлено")
+      },
+      ("b", None) => {
+         // Предложим выбрать срок блокировки
+         let _= cx.requester
+         .edit_message_reply_markup(chat_id, message_id)
+         .reply_markup(ban_markup(submission.id))
+         .send()
+         .await;
+         String::from("Выберите срок блокировки")
+      },
+      ("a", None) => {
+         // Вернём исходные кнопки
+         let markup = if submission.status == "pending" {
+            admin_markup(submission.id, &db::settings(&submission.chat_name).await.quorum, db::votes(submission.id).await)
+         } else {
+            post_markup(submission.id)
+         };
+         let _= cx.requester
+         .edit_message_reply_markup(chat_id, message_id)
+         .reply_markup(markup)
+         .send()
+         .await;
+         String::from("Выберите действие")
+      },
+      ("b", Some(days)) => {
+         let days = match days.parse::<i32>() {
+            Ok(days) => days,
+            Err(_) => return String::from("Слишком старое сообщение"),
+         };
+
+         // Блокировка автора ожидающего модерации сообщения засчитывается как голос против, а опубликованное
+         // остаётся в чате, пока администратор его не удалит
+         let pending = submission.status == "pending";
+         if !pending && submission.status != "approved" {
+            return String::from("Решение уже принято");
+         }
+
+         // Сохраняем только хеш автора, чтобы его нельзя было узнать даже по базе блокировок
+         let ban_id = match db::ban(&submission.chat_name, &submission.author_hash, days).await {
+            Some(ban_id) => ban_id,
+            None => return String::from("Ошибка блокировки"),
+         };
+         let duration = if days > 0 { format!("на {} дн.", days) } else { String::from("навсегда") };
+         let banned = format!("автор заблокирован {}, блокировка #{}", duration, ban_id);
+
+         if pending {
+            let res = cast_vote(&cx.requester, admin_id, (chat_id, message_id), &submission, user_id, false, format!("{}; {}", by, banned)).await;
+            format!("{}, автор заблокирован", res)
+         } else {
+            let _= edit_card(&cx.requester, chat_id, message_id, &signed(&format!("Опубликовано, {}", banned)), &submission)
+            .reply_markup(InlineKeyboardMarkup::default()
+               .append_row(vec![InlineKeyboardButton::callback(String::from("🗑 Удалить из чата"), format!("d{}", submission.id))]))
+            .send()
+            .await;
+            String::from("Автор заблокирован")
+         }
+      },
+      _ => String::from("Слишком старое сообщение"),
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn filter_mode() {
+      assert!(effective_mode(Mode::Filter, true) == Mode::Manual);
+      assert!(effective_mode(Mode::Filter, false) == Mode::Auto);
+      for mode in [Mode::Manual, Mode::Auto, Mode::Post] {
+         assert!(effective_mode(mode, true) == mode && effective_mode(mode, false) == mode, "{}", mode.as_str());
+      }
+   }
+
+   #[test]
+   fn report_after_publication() {
+      // Успешная публикация в режиме auto модераторам не видна, а в режиме post приходит карточка
+      assert_eq!(report(Mode::Auto, Ok(()), "текст"), Report::Nothing);
+      assert_eq!(report(Mode::Post, Ok(()), "текст"), Report::Card(String::from("Опубликовано:\nтекст")));
+
+      // Об ошибке сообщается в обоих режимах, текст ошибки экранируется
+      for mode in [Mode::Auto, Mode::Post] {
+         assert_eq!(report(mode, Err(String::from("Bad Request: <chat> not found")), "текст"),
+            Report::Failure(String::from("Не удалось опубликовать сообщение (Bad Request: &lt;chat&gt; not found):\nтекст")));
+      }
+   }
+}
@@ -0,0 +1,145 @@
+/* ===============================================================================
+Бот для анонимизации сообщений для чата.
+Выбор чата для отправки: страницы, поиск и сортировка. 19 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use arraylib::iter::IteratorExt;
+use teloxide::types::{InlineKeyboardMarkup, InlineKeyboardButton, };
+use crate::database as db;
+
+/// Начало текста сообщения с выбором чата, по нему узнаётся ответ с поиском
+pub const PROMPT: &str = "Выберите чат для отправки";
+
+// Чатов на одной странице
+const PAGE_SIZE: i64 = 10;
+
+// Данные кнопки ограничены 64 байтами, на строку поиска остаётся не больше
+const SEARCH_LIMIT: usize = 48;
+
+/// Порядок чатов в списке
+#[derive(Clone, Copy, PartialEq)]
+pub enum Order {
+   Popular, // сначала те, куда чаще отправляют
+   Recent,  // сначала те, куда отправляли недавно
+}
+
+impl Order {
+   fn as_char(&self) -> char {
+      match self {
+         Order::Popular => 'p',
+         Order::Recent => 'r',
+      }
+   }
+}
+
+/// Состояние списка чатов, хранится в данных кнопок навигации
+pub struct Picker {
+   pub page: i64,
+   pub order: Order,
+   pub search: String,
+}
+
+impl Default for Picker {
+   fn default() -> Self {
+      Self { page: 0, order: Order::Popular, search: String::new() }
+   }
+}
+
+impl Picker {
+   /// Список с поиском по части названия
+   pub fn search(search: &str) -> Self {
+      let search = search.trim().chars()
+      .scan(0, |len, c| { *len += c.len_utf8(); if *len <= SEARCH_LIMIT { Some(c) } else { None } })
+      .collect();
+      Self { search, ..Self::default() }
+   }
+
+   /// Разбирает данные кнопки навигации вида "p2 r строка поиска"
+   pub fn parse(data: &str) -> Option<Self> {
+      let mut parts = data.strip_prefix('p')?.splitn(3, ' ');
+      let page = parts.next()?.parse().ok()?;
+      let order = match parts.next()? {
+         "p" => Order::Popular,
+         "r" => Order::Recent,
+         _ => return None,
+      };
+      let search = String::from(parts.next().unwrap_or_default());
+      Some(Self { page, order, search })
+   }
+
+   // Данные для кнопки, ведущей к указанной странице и порядку
+   fn data(&self, page: i64, order: Order) -> String {
+      format!("p{} {} {}", page, order.as_char(), self.search)
+   }
+
+   /// Текст сообщения с выбором чата
+   pub fn text(&self) -> String {
+      if self.search.is_empty() {
+         format!("{}. Чтобы найти чат, ответьте на это сообщение частью его названия", PROMPT)
+      } else {
+         format!("{}, поиск «{}». Чтобы искать другое, ответьте на это сообщение частью названия чата", PROMPT, self.search)
+      }
+   }
+
+   /// Кнопки с чатами текущей страницы и навигацией, на первой странице
+   /// первым идёт последний выбранный пользователем чат
+   pub async fn markup(&self, user_id: i64) -> InlineKeyboardMarkup {
+      let (chats, total) = db::chats(&self.search, self.order, self.page * PAGE_SIZE, PAGE_SIZE).await;
+
+      // Создадим кнопки
+      let mut buttons: Vec<InlineKeyboardButton> = chats.into_iter()
+      .map(|chat_name| InlineKeyboardButton::callback(chat_name.clone(), chat_name)).collect();
+
+      // Последняя непарная кнопка, если есть
+      let last = if buttons.len() % 2 == 1 { buttons.pop() } else { None };
+
+      // Быстрый выбор прежнего чата, он может быть и скрыт из списка
+      let mut markup = InlineKeyboardMarkup::default();
+      if self.page == 0 && self.search.is_empty() {
+         if let Some(last_chat) = db::last_chat(user_id).await {
+            markup = markup.append_row(vec![InlineKeyboardButton::callback(format!("↻ Снова в {}", last_chat), last_chat)]);
+         }
+      }
+
+      // Поделим по две в ряд
+      let mut markup = IteratorExt::array_chunks::<[_; 2]>(buttons.into_iter())
+      .fold(markup, |acc, [left, right]| acc.append_row(vec![left, right]));
+
+      // Добавляем последнюю непарную кнопку, если есть
+      if let Some(last_button) = last {
+         markup = markup.append_row(vec![last_button]);
+      }
+
+      // Переход между страницами
+      let pages = (total + PAGE_SIZE - 1) / PAGE_SIZE;
+      if pages > 1 {
+         let mut row = Vec::new();
+         if self.page > 0 {
+            row.push(InlineKeyboardButton::callback(String::from("◀"), self.data(self.page - 1, self.order)));
+         }
+         row.push(InlineKeyboardButton::callback(format!("{}/{}", self.page + 1, pages), self.data(self.page, self.order)));
+         if self.page + 1 < pages {
+            row.push(InlineKeyboardButton::callback(String::from("▶"), self.data(self.page + 1, self.order)));
+         }
+         markup = markup.append_row(row);
+      }
+
+      // Порядок, текущий отмечен, при смене начинаем с первой страницы
+      let mark = |order: Order, caption: &str| {
+         let caption = if order == self.order { format!("• {}", caption) } else { String::from(caption) };
+         InlineKeyboardButton::callback(caption, self.data(0, order))
+      };
+      markup = markup.append_row(vec![mark(Order::Popular, "Популярные"), mark(Order::Recent, "Недавние")]);
+
+      // Сброс поиска
+      if !self.search.is_empty() {
+         markup = markup.append_row(vec![InlineKeyboardButton::callback(String::from("✖ Все чаты"), format!("p0 {} ", self.order.as_char()))]);
+      }
+
+      markup
+   }
+}
@@ -0,0 +1,167 @@
+/* ===============================================================================
+Бот для анонимизации сообщений для чата.
+Анонимные опросы. 19 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use teloxide::{
+   types::{self, PollType},
+   utils::html,
+};
+
+// Ограничения Telegram на опросы, вариантов ответа с Bot API 9.1 может быть до 12
+const QUESTION_LIMIT: usize = 300;
+const OPTION_LIMIT: usize = 100;
+const MIN_OPTIONS: usize = 2;
+const MAX_OPTIONS: usize = 12;
+
+/// Пояснение к команде /poll
+pub const HELP: &str = "Создайте опрос кнопкой со скрепкой и отправьте его мне или отправьте команду /poll, а за ней вопрос и с новых строк варианты ответа, например:
+/poll Где встретимся?
+В парке
+В кафе
+Для опроса с несколькими ответами начните вопрос с +, для викторины отметьте правильный ответ знаком * в начале";
+
+/// Опрос, публикуемый ботом от своего имени
+#[derive(Clone)]
+pub struct Poll {
+   pub question: String,
+   pub options: Vec<String>,
+   // Можно ли выбрать несколько ответов
+   pub multiple: bool,
+   // Номер правильного ответа для викторины
+   pub correct: Option<i32>,
+}
+
+impl Poll {
+   /// Опрос из присланного пользователем
+   pub fn from_telegram(poll: &types::Poll) -> Self {
+      Self {
+         question: poll.question.clone(),
+         options: poll.options.iter().map(|option| option.text.clone()).collect(),
+         multiple: poll.allows_multiple_answers,
+         correct: if poll.poll_type == PollType::Quiz { poll.correct_option_id } else { None },
+      }
+   }
+
+   /// Разбирает текст команды /poll: вопрос в первой строке, варианты в следующих
+   pub fn parse_command(text: &str) -> Result<Self, String> {
+      let text = text.trim_start().strip_prefix("/poll").unwrap_or(text);
+      let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+
+      let question = lines.next().unwrap_or_default();
+      let (question, multiple) = match question.strip_prefix('+') {
+         Some(question) => (question.trim(), true),
+         None => (question, false),
+      };
+
+      let mut correct = None;
+      let mut options = Vec::new();
+      for (i, option) in lines.enumerate() {
+         match option.strip_prefix('*') {
+            Some(option) if correct.is_none() => {
+               correct = Some(i as i32);
+               options.push(String::from(option.trim()));
+            }
+            Some(_) => return Err(String::from("У викторины может быть только один правильный ответ")),
+            None => options.push(String::from(option)),
+         }
+      }
+
+      let res = Self { question: String::from(question), options, multiple: multiple && correct.is_none(), correct };
+      res.check()?;
+      Ok(res)
+   }
+
+   /// Проверяет ограничения Telegram, при ошибке возвращает пояснение для автора
+   pub fn check(&self) -> Result<(), String> {
+      if self.question.is_empty() || self.question.chars().count() > QUESTION_LIMIT {
+         return Err(format!("Вопрос должен быть не длиннее {} символов", QUESTION_LIMIT));
+      }
+      if self.options.len() < MIN_OPTIONS || self.options.len() > MAX_OPTIONS {
+         return Err(format!("Вариантов ответа должно быть от {} до {}", MIN_OPTIONS, MAX_OPTIONS));
+      }
+      if self.options.iter().any(|option| option.is_empty() || option.chars().count() > OPTION_LIMIT) {
+         return Err(format!("Варианты ответа должны быть не пустыми и не длиннее {} символов", OPTION_LIMIT));
+      }
+      Ok(())
+   }
+
+   /// Вид опроса для хранения: regular, multiple или quiz
+   pub fn kind(&self) -> &'static str {
+      match (self.correct, self.multiple) {
+         (Some(_), _) => "quiz",
+         (None, true) => "multiple",
+         (None, false) => "regular",
+      }
+   }
+
+   /// Вид опроса для Telegram
+   pub fn poll_type(&self) -> PollType {
+      if self.correct.is_some() { PollType::Quiz } else { PollType::Regular }
+   }
+
+   /// Опрос в HTML для предпросмотра и модератора
+   pub fn html(&self) -> String {
+      let kind = match self.kind() {
+         "quiz" => " (викторина)",
+         "multiple" => " (несколько ответов)",
+         _ => "",
+      };
+      self.options.iter().enumerate()
+      .fold(format!("📊 <b>{}</b>{}", html::escape(&self.question), kind), |acc, (i, option)| {
+         let mark = if self.correct == Some(i as i32) { "✔" } else { "•" };
+         format!("{}\n{} {}", acc, mark, html::escape(option))
+      })
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn regular_poll() {
+      let poll = Poll::parse_command("/poll Как дела?\n хорошо \n\nплохо\n").unwrap();
+      assert_eq!(poll.question, "Как дела?");
+      assert_eq!(poll.options, vec!["хорошо", "плохо"]);
+      assert_eq!((poll.kind(), poll.correct), ("regular", None));
+   }
+
+   #[test]
+   fn multiple_answers() {
+      let poll = Poll::parse_command("/poll\n+ Что взять?\nчай\nкофе\nсок").unwrap();
+      assert_eq!(poll.question, "Что взять?");
+      assert_eq!(poll.options.len(), 3);
+      assert_eq!(poll.kind(), "multiple");
+   }
+
+   #[test]
+   fn quiz() {
+      let poll = Poll::parse_command("/poll +2+2?\n3\n* 4\n5").unwrap();
+      assert_eq!(poll.options, vec!["3", "4", "5"]);
+      assert_eq!(poll.correct, Some(1));
+      // У викторины не бывает нескольких ответов
+      assert_eq!(poll.kind(), "quiz");
+   }
+
+   #[test]
+   fn errors() {
+      assert!(Poll::parse_command("/poll").is_err());
+      assert!(Poll::parse_command("/poll Вопрос?\nединственный").is_err());
+      assert!(Poll::parse_command("/poll Вопрос?\n*да\n*тоже да").is_err());
+      assert!(Poll::parse_command("/poll Вопрос?\n*\nнет").is_err());
+      assert!(Poll::parse_command(&format!("/poll {}\nда\nнет", "в".repeat(QUESTION_LIMIT + 1))).is_err());
+      assert!(Poll::parse_command(&format!("/poll Вопрос?\nда\n{}", "н".repeat(OPTION_LIMIT + 1))).is_err());
+   }
+
+   #[test]
+   fn options_limit() {
+      let options = |count: usize| (1..=count).map(|i| i.to_string()).collect::<Vec<_>>().join("\n");
+      assert_eq!(Poll::parse_command(&format!("/poll Вопрос?\n{}", options(MAX_OPTIONS))).unwrap().options.len(), MAX_OPTIONS);
+      assert!(Poll::parse_command(&format!("/poll Вопрос?\n{}", options(MAX_OPTIONS + 1))).is_err());
+   }
+}
@@ -0,0 +1,197 @@
+/* ===============================================================================
+Бот для анонимизации сообщений для чата.
+Голосование модераторов и сроки рассмотрения сообщений. 19 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use std::{fmt, str::FromStr};
+
+// Наибольшее число голосов для решения
+const MAX_VOTES: u32 = 20;
+
+// Сроки рассмотрения, от минуты до недели
+const MIN_TIMEOUT: u32 = 60;
+const MAX_TIMEOUT: u32 = 604800;
+
+/// Решение по сообщению
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Verdict {
+   Approve,
+   Reject,
+   // Срок рассмотрения истёк, сообщение не публикуется
+   Expire,
+}
+
+impl FromStr for Verdict {
+   type Err = ();
+
+   fn from_str(s: &str) -> Result<Self, Self::Err> {
+      match s {
+         "approve" => Ok(Verdict::Approve),
+         "reject" => Ok(Verdict::Reject),
+         "expire" => Ok(Verdict::Expire),
+         _ => Err(()),
+      }
+   }
+}
+
+impl Verdict {
+   pub fn as_str(&self) -> &'static str {
+      match self {
+         Verdict::Approve => "approve",
+         Verdict::Reject => "reject",
+         Verdict::Expire => "expire",
+      }
+   }
+}
+
+/// Сколько голосов модераторов нужно для решения
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Quorum {
+   pub approvals: u32,
+   pub rejections: u32,
+}
+
+impl Default for Quorum {
+   fn default() -> Self {
+      Self { approvals: 1, rejections: 1 }
+   }
+}
+
+impl FromStr for Quorum {
+   type Err = String;
+
+   fn from_str(s: &str) -> Result<Self, Self::Err> {
+      let help = || String::from("Кворум задаётся как 'одобрений отклонений', например '/set quorum 3 2'. Решение по умолчанию, если голосов не набралось вовремя, задаётся настройкой sla");
+      let (approvals, rejections) = match s.split_whitespace().collect::<Vec<_>>().as_slice() {
+         [approvals, rejections] => (approvals.parse::<u32>().map_err(|_| help())?, rejections.parse::<u32>().map_err(|_| help())?),
+         _ => return Err(help()),
+      };
+      if !(1..=MAX_VOTES).contains(&approvals) || !(1..=MAX_VOTES).contains(&rejections) {
+         return Err(format!("Число голосов должно быть от 1 до {}", MAX_VOTES));
+      }
+      Ok(Self { approvals, rejections })
+   }
+}
+
+impl fmt::Display for Quorum {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      write!(f, "{} {}", self.approvals, self.rejections)
+   }
+}
+
+impl Quorum {
+   /// Решают ли модераторы голосованием, а не первым нажатием
+   pub fn is_vote(&self) -> bool {
+      self.approvals > 1 || self.rejections > 1
+   }
+
+   /// Решение по набранным голосам, если их уже достаточно
+   pub fn outcome(&self, approvals: i64, rejections: i64) -> Option<Verdict> {
+      if approvals >= i64::from(self.approvals) {
+         Some(Verdict::Approve)
+      } else if rejections >= i64::from(self.rejections) {
+         Some(Verdict::Reject)
+      } else {
+         None
+      }
+   }
+}
+
+/// Сроки рассмотрения: через сколько секунд после отправки на модерацию напомнить
+/// модераторам, когда передать сообщение запасным модераторам и когда принять решение
+/// по умолчанию, сообщив о нём автору
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct Sla {
+   pub remind: Option<u32>,
+   pub escalate: Option<u32>,
+   pub expire: Option<(u32, Verdict)>,
+}
+
+impl FromStr for Sla {
+   type Err = String;
+
+   fn from_str(s: &str) -> Result<Self, Self::Err> {
+      if s.trim() == "off" {
+         return Ok(Self::default());
+      }
+
+      let help = || String::from("Сроки задаются как 'напоминание передача' в секундах, 0 - не нужно, и, если нужно, окончательный срок и решение по его истечении (approve, reject или expire), например '/set sla 3600 14400', '/set sla 3600 0 86400 expire' или '/set sla off'");
+      let words: Vec<&str> = s.split_whitespace().collect();
+      let (remind, escalate, expire) = match words.as_slice() {
+         [remind, escalate] => (remind, escalate, None),
+         [remind, escalate, timeout, verdict] => {
+            let verdict = verdict.parse::<Verdict>().map_err(|_| String::from("Решение по истечении окончательного срока может быть approve, reject или expire"))?;
+            (remind, escalate, Some((timeout.parse::<u32>().map_err(|_| help())?, verdict)))
+         }
+         _ => return Err(help()),
+      };
+      let remind = remind.parse::<u32>().map_err(|_| help())?;
+      let escalate = escalate.parse::<u32>().map_err(|_| help())?;
+
+      // Ноль означает, что этот шаг не нужен
+      let check = |value: u32| match value {
+         0 => Ok(None),
+         value if (MIN_TIMEOUT..=MAX_TIMEOUT).contains(&value) => Ok(Some(value)),
+         _ => Err(format!("Сроки должны быть от {} до {} сек.", MIN_TIMEOUT, MAX_TIMEOUT)),
+      };
+      let expire = match expire {
+         Some((timeout, verdict)) => check(timeout)?.map(|timeout| (timeout, verdict)),
+         None => None,
+      };
+      Ok(Self { remind: check(remind)?, escalate: check(escalate)?, expire })
+   }
+}
+
+impl fmt::Display for Sla {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      match (self.remind, self.escalate, self.expire) {
+         (None, None, None) => write!(f, "off"),
+         (remind, escalate, None) => write!(f, "{} {}", remind.unwrap_or_default(), escalate.unwrap_or_default()),
+         (remind, escalate, Some((timeout, verdict))) => write!(f, "{} {} {} {}", remind.unwrap_or_default(), escalate.unwrap_or_default(), timeout, verdict.as_str()),
+      }
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn parse_quorum() {
+      assert_eq!("3 2".parse(), Ok(Quorum { approvals: 3, rejections: 2 }));
+      assert_eq!(" 1  20 ".parse(), Ok(Quorum { approvals: 1, rejections: 20 }));
+      assert_eq!("3 2".parse::<Quorum>().unwrap().to_string(), "3 2");
+      for s in ["", "3", "0 1", "1 21", "a b", "-1 2", "3 2 3600 reject"] {
+         assert!(s.parse::<Quorum>().is_err(), "{}", s);
+      }
+   }
+
+   #[test]
+   fn parse_sla() {
+      assert_eq!("off".parse(), Ok(Sla::default()));
+      assert_eq!("0 0".parse(), Ok(Sla::default()));
+      assert_eq!("3600 14400".parse(), Ok(Sla { remind: Some(3600), escalate: Some(14400), expire: None }));
+      assert_eq!("3600 0 86400 expire".parse(), Ok(Sla { remind: Some(3600), escalate: None, expire: Some((86400, Verdict::Expire)) }));
+      assert_eq!("0 0 60 approve".parse(), Ok(Sla { remind: None, escalate: None, expire: Some((60, Verdict::Approve)) }));
+      // Нулевой окончательный срок означает, что решения по умолчанию нет
+      assert_eq!("60 0 0 reject".parse(), Ok(Sla { remind: Some(60), escalate: None, expire: None }));
+   }
+
+   #[test]
+   fn sla_display_round_trip() {
+      for s in ["off", "3600 14400", "0 600", "3600 0 86400 expire", "0 0 604800 reject"] {
+         assert_eq!(s.parse::<Sla>().unwrap().to_string(), s);
+      }
+   }
+
+   #[test]
+   fn invalid_sla() {
+      for s in ["", "3600", "59 0", "0 604801", "60 60 60", "60 60 60 later", "60 60 59 approve", "a b"] {
+         assert!(s.parse::<Sla>().is_err(), "{}", s);
+      }
+   }
+}
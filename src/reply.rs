@@ -0,0 +1,96 @@
+/* ===============================================================================
+Бот для анонимизации сообщений для чата.
+Анонимные ответы на сообщения чата по ссылке. 19 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use teloxide::types::MessageEntity;
+
+// Ссылка на сообщение публичного чата, в том числе в теме форума
+static LINK: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?i:https?://)?(?i:t\.me|telegram\.me)/([A-Za-z0-9_]{4,})/(?:\d+/)?(\d+)(?:[?#]\S*)?$").unwrap());
+
+/// Сообщение чата, на которое отвечает автор
+pub struct Reply {
+   pub chat_name: String,
+   pub message_id: i32,
+}
+
+impl Reply {
+   /// Ссылка на сообщение
+   pub fn link(&self) -> String {
+      format!("https://t.me/{}/{}", self.chat_name.trim_start_matches('@'), self.message_id)
+   }
+}
+
+/// Если первая строка текста - ссылка на сообщение чата, отделяет её. Возвращает сообщение,
+/// если оно указано, оставшийся текст и его разметку со сдвинутыми смещениями
+pub fn split(text: &str, entities: &[MessageEntity]) -> (Option<Reply>, String, Vec<MessageEntity>) {
+   let (first, rest) = text.split_once('\n').unwrap_or((text, ""));
+   let reply = LINK.captures(first.trim()).and_then(|caps| Some(Reply {
+      chat_name: format!("@{}", &caps[1]),
+      message_id: caps[2].parse().ok()?,
+   }));
+   let reply = match reply {
+      Some(reply) => reply,
+      None => return (None, String::from(text), entities.to_vec()),
+   };
+
+   // Смещения разметки указаны в единицах UTF-16, отрезанная часть включает перевод строки
+   let cut = text.len() - rest.len();
+   let shift = text[..cut].encode_utf16().count();
+   let entities = entities.iter()
+   .filter(|entity| entity.offset >= shift)
+   .map(|entity| MessageEntity { offset: entity.offset - shift, ..entity.clone() })
+   .collect();
+
+   (Some(reply), String::from(rest), entities)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use teloxide::types::MessageEntityKind;
+
+   #[test]
+   fn link_is_split_off() {
+      let entities = [MessageEntity::new(MessageEntityKind::Url, 0, 25), MessageEntity::new(MessageEntityKind::Bold, 26, 5)];
+      let (reply, text, entities) = split("https://t.me/some_chat/42\nhello", &entities);
+      let reply = reply.unwrap();
+      assert_eq!((reply.chat_name.as_str(), reply.message_id), ("@some_chat", 42));
+      assert_eq!(text, "hello");
+      assert_eq!(entities, vec![MessageEntity::new(MessageEntityKind::Bold, 0, 5)]);
+   }
+
+   #[test]
+   fn link_variants() {
+      for link in ["t.me/some_chat/42", "HTTP://Telegram.me/some_chat/42", "https://t.me/some_chat/7/42", "https://t.me/some_chat/42?single", " t.me/some_chat/42 "] {
+         let (reply, text, _) = split(link, &[]);
+         assert_eq!(reply.map(|reply| reply.link()), Some(String::from("https://t.me/some_chat/42")), "{}", link);
+         assert_eq!(text, "");
+      }
+   }
+
+   #[test]
+   fn entities_are_shifted() {
+      // Разметка самой ссылки отбрасывается, остальная сдвигается на длину первой строки
+      let entities = [MessageEntity::new(MessageEntityKind::Url, 0, 17), MessageEntity::new(MessageEntityKind::Bold, 21, 2)];
+      let (_, text, entities) = split("t.me/some_chat/42\n😀 ab", &entities);
+      assert_eq!(text, "😀 ab");
+      assert_eq!(entities, vec![MessageEntity::new(MessageEntityKind::Bold, 3, 2)]);
+   }
+
+   #[test]
+   fn text_without_link_is_kept() {
+      let entities = [MessageEntity::new(MessageEntityKind::Bold, 0, 5)];
+      for text in ["hello\nt.me/some_chat/42", "t.me/abc/42", "t.me/some_chat/x", "see t.me/some_chat/42"] {
+         let (reply, rest, kept) = split(text, &entities);
+         assert!(reply.is_none(), "{}", text);
+         assert_eq!((rest.as_str(), kept.as_slice()), (text, &entities[..]));
+      }
+   }
+}
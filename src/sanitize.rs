@@ -0,0 +1,266 @@
+/* ===============================================================================
+Бот для анонимизации сообщений для чата.
+Очистка текста от признаков, по которым можно опознать автора. 19 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::Url;
+use unicode_normalization::UnicodeNormalization;
+use teloxide::types::MessageEntity;
+use crate::formatting;
+
+// Ссылки в тексте
+static URLS: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(?i)\bhttps?://[^\s<>"]+"#).unwrap());
+
+// Параметры ссылок, по которым сайты отслеживают, кто и откуда поделился ссылкой
+const TRACKING: &[&str] = &["fbclid", "gclid", "dclid", "gbraid", "wbraid", "msclkid", "yclid", "ysclid",
+   "igshid", "igsh", "si", "mc_cid", "mc_eid", "_hsenc", "_hsmi", "mkt_tok", "ref_src", "ref_url", "_openstat",
+];
+
+// Параметры отслеживания, которые на других сайтах могут иметь смысл
+const TRACKING_BY_HOST: &[(&str, &[&str])] = &[
+   ("twitter.com", &["s", "t"]),
+   ("x.com", &["s", "t"]),
+   ("tiktok.com", &["_r", "_t", "is_from_webapp", "sender_device"]),
+];
+
+/// Результат очистки текста
+pub struct Cleaned {
+   pub text: String,
+   // Что было изменено, для модератора
+   pub changes: Vec<String>,
+}
+
+/// Приводит текст к стандартной форме юникода, удаляет невидимые и управляющие символы,
+/// а из ссылок параметры отслеживания
+fn clean(text: &str) -> Cleaned {
+   let mut changes = Vec::new();
+
+   // Разные способы записи одного и того же символа могут служить меткой
+   let normalized: String = text.nfkc().collect();
+   if normalized != text {
+      changes.push(String::from("текст приведён к стандартной форме юникода"));
+   }
+
+   // Невидимые символы
+   let (visible, removed) = strip_invisible(&normalized);
+   if removed > 0 {
+      changes.push(format!("удалено невидимых и управляющих символов: {}", removed));
+   }
+
+   // Параметры отслеживания в ссылках
+   let text = URLS.replace_all(&visible, |caps: &regex::Captures| {
+      // Знаки препинания в конце обычно не относятся к ссылке
+      let found = &caps[0];
+      let url = found.trim_end_matches(|c| ".,;:!?)".contains(c));
+      let tail = &found[url.len()..];
+
+      let (cleaned, params) = clean_url(url);
+      if !params.is_empty() {
+         changes.push(format!("из ссылки удалены параметры отслеживания: {}", params.join(", ")));
+      }
+      format!("{}{}", cleaned, tail)
+   }).into_owned();
+
+   Cleaned { text, changes }
+}
+
+/// Очищает текст с разметкой Telegram, каждый размеченный кусок отдельно. Возвращает
+/// очищенный простой текст с перечнем изменений и HTML для публикации
+pub fn clean_formatted(text: &str, entities: &[MessageEntity]) -> (Cleaned, String) {
+   let mut text_changes = Vec::new();
+   let mut url_changes = Vec::new();
+
+   let (html, plain) = formatting::to_html(text, entities,
+      |piece| {
+         let cleaned = clean(piece);
+         text_changes.extend(cleaned.changes);
+         cleaned.text
+      },
+      |url| {
+         let (cleaned, params) = clean_url(url);
+         if !params.is_empty() {
+            url_changes.push(format!("из ссылки удалены параметры отслеживания: {}", params.join(", ")));
+         }
+         cleaned
+      },
+   );
+
+   // Одинаковые изменения в разных кусках перечислим один раз
+   let mut changes = Vec::new();
+   for change in text_changes.into_iter().chain(url_changes) {
+      if !changes.contains(&change) {
+         changes.push(change);
+      }
+   }
+
+   (Cleaned { text: plain, changes }, html)
+}
+
+/// Удаляет из ссылки параметры отслеживания, возвращает новую ссылку и названия удалённых параметров
+fn clean_url(url: &str) -> (String, Vec<String>) {
+   let mut parsed = match Url::parse(url) {
+      Ok(parsed) => parsed,
+      Err(_) => return (String::from(url), Vec::new()),
+   };
+
+   // Параметры, специфичные для сайта
+   let host = parsed.host_str().unwrap_or_default().trim_start_matches("www.").to_lowercase();
+   let by_host = TRACKING_BY_HOST.iter()
+   .find(|(name, _)| host == *name || host.ends_with(&format!(".{}", name)))
+   .map_or(&[][..], |(_, params)| params);
+
+   let (kept, removed): (Vec<_>, Vec<_>) = parsed.query_pairs()
+   .into_owned()
+   .partition(|(key, _)| {
+      let key = key.to_lowercase();
+      !(key.starts_with("utm_") || TRACKING.contains(&key.as_str()) || by_host.contains(&key.as_str()))
+   });
+
+   if removed.is_empty() {
+      return (String::from(url), Vec::new());
+   }
+
+   if kept.is_empty() {
+      parsed.set_query(None);
+   } else {
+      parsed.query_pairs_mut().clear().extend_pairs(kept);
+   }
+
+   (parsed.to_string(), removed.into_iter().map(|(key, _)| key).collect())
+}
+
+/// Удаляет невидимые символы, возвращает очищенный текст и число удалённых символов
+fn strip_invisible(text: &str) -> (String, usize) {
+   let chars: Vec<char> = text.chars().collect();
+   let mut res = String::with_capacity(text.len());
+   let mut removed = 0;
+
+   for (i, &c) in chars.iter().enumerate() {
+      // Соединитель нулевой ширины нужен для составных эмодзи, оставим его между ними,
+      // а селектор эмодзи-начертания только сразу после символа, у которого оно есть
+      let keep = match c {
+         '\u{200D}' => i > 0 && is_emoji(chars[i - 1]) && chars.get(i + 1).is_some_and(|&next| is_emoji(next)),
+         '\u{FE0F}' => res.chars().last().is_some_and(|prev| {
+            (is_emoji(prev) && prev != '\u{FE0F}') || (is_keycap(prev) && chars.get(i + 1) == Some(&'\u{20E3}'))
+         }),
+         c => !is_invisible(c),
+      };
+
+      if keep {
+         res.push(c);
+      } else {
+         removed += 1;
+      }
+   }
+
+   (res, removed)
+}
+
+// Символы нулевой ширины, управления направлением текста и прочие невидимые
+fn is_invisible(c: char) -> bool {
+   matches!(c,
+      '\u{00AD}'                    // мягкий перенос
+      | '\u{034F}'                  // невидимый соединитель графем
+      | '\u{061C}'                  // арабская метка направления
+      | '\u{115F}' | '\u{1160}'     // заполнители хангыля
+      | '\u{17B4}' | '\u{17B5}'     // невидимые гласные кхмерского
+      | '\u{180B}'..='\u{180F}'     // монгольские селекторы и разделитель
+      | '\u{200B}'..='\u{200F}'     // нулевой ширины и метки направления
+      | '\u{202A}'..='\u{202E}'     // встраивание и переопределение направления
+      | '\u{2060}'..='\u{2064}'     // соединитель слов и невидимые операторы
+      | '\u{2066}'..='\u{206F}'     // изоляция направления и устаревшие управляющие
+      | '\u{3164}' | '\u{FFA0}'     // заполнители хангыля
+      | '\u{FE00}'..='\u{FE0F}'     // селекторы вариантов
+      | '\u{FEFF}'                  // неразрывный пробел нулевой ширины
+      | '\u{FFF9}'..='\u{FFFB}'     // аннотации
+      | '\u{E0000}'..='\u{E007F}'   // теги
+      | '\u{E0100}'..='\u{E01EF}'   // дополнительные селекторы вариантов
+   )
+}
+
+// Примерная проверка, что символ относится к эмодзи
+fn is_emoji(c: char) -> bool {
+   matches!(c,
+      '\u{00A9}' | '\u{00AE}' | '\u{203C}' | '\u{2049}' | '\u{2122}' | '\u{2139}'
+      | '\u{2194}'..='\u{21AA}' | '\u{231A}'..='\u{23FF}' | '\u{24C2}' | '\u{25AA}'..='\u{25FE}'
+      | '\u{2600}'..='\u{27BF}' | '\u{2934}' | '\u{2935}' | '\u{2B05}'..='\u{2B55}'
+      | '\u{3030}' | '\u{303D}' | '\u{3297}' | '\u{3299}'
+      | '\u{FE0F}' | '\u{1F000}'..='\u{1FAFF}'
+   )
+}
+
+// Основа эмодзи-клавиши вроде 1️⃣
+fn is_keycap(c: char) -> bool {
+   c.is_ascii_digit() || c == '#' || c == '*'
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn plain_text_is_unchanged() {
+      let cleaned = clean("Обычный текст, https://example.com/page?id=1");
+      assert_eq!(cleaned.text, "Обычный текст, https://example.com/page?id=1");
+      assert!(cleaned.changes.is_empty());
+   }
+
+   #[test]
+   fn invisible_characters_are_removed() {
+      let cleaned = clean("a\u{200B}b\u{202E}c\u{2063}d\u{E0041}e\u{180F}f\u{FE00}g\u{FE0E}h\u{E0100}i\u{00AD}j");
+      assert_eq!(cleaned.text, "abcdefghij");
+      assert_eq!(cleaned.changes, vec![String::from("удалено невидимых и управляющих символов: 9")]);
+   }
+
+   #[test]
+   fn emoji_selector_only_after_emoji() {
+      // После эмодзи селектор нужен, после буквы и повторный - это метка
+      assert_eq!(clean("❤\u{FE0F}").text, "❤\u{FE0F}");
+      assert_eq!(clean("©\u{FE0F}").text, "©\u{FE0F}");
+      assert_eq!(clean("a\u{FE0F}b").text, "ab");
+      assert_eq!(clean("\u{FE0F}").text, "");
+      assert_eq!(clean("❤\u{FE0F}\u{FE0F}").text, "❤\u{FE0F}");
+      // Клавиша 1️⃣, но не цифра с селектором без неё
+      assert_eq!(clean("1\u{FE0F}\u{20E3}").text, "1\u{FE0F}\u{20E3}");
+      assert_eq!(clean("1\u{FE0F}2").text, "12");
+   }
+
+   #[test]
+   fn zero_width_joiner_only_inside_emoji() {
+      let family = "👨\u{200D}👩\u{200D}👧";
+      assert_eq!(clean(family).text, family);
+      let heart_on_fire = "❤\u{FE0F}\u{200D}🔥";
+      assert_eq!(clean(heart_on_fire).text, heart_on_fire);
+      assert_eq!(clean("a\u{200D}b").text, "ab");
+      assert_eq!(clean("👨\u{200D}").text, "👨");
+   }
+
+   #[test]
+   fn text_is_normalized() {
+      // Полноширинные буквы и лигатура приводятся к обычным
+      let cleaned = clean("ＡＢ ﬁ");
+      assert_eq!(cleaned.text, "AB fi");
+      assert_eq!(cleaned.changes, vec![String::from("текст приведён к стандартной форме юникода")]);
+   }
+
+   #[test]
+   fn tracking_parameters_are_removed() {
+      let cleaned = clean("см. https://example.com/a?utm_source=tg&id=5&fbclid=x, и https://x.com/u/status/1?s=20.");
+      assert_eq!(cleaned.text, "см. https://example.com/a?id=5, и https://x.com/u/status/1.");
+      assert_eq!(cleaned.changes, vec![
+         String::from("из ссылки удалены параметры отслеживания: utm_source, fbclid"),
+         String::from("из ссылки удалены параметры отслеживания: s"),
+      ]);
+   }
+
+   #[test]
+   fn site_specific_parameters_are_kept_elsewhere() {
+      assert_eq!(clean("https://example.com/?s=query&t=1").text, "https://example.com/?s=query&t=1");
+   }
+}
@@ -0,0 +1,229 @@
+/* ===============================================================================
+Бот для анонимизации сообщений для чата.
+Настройки чата. 19 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use std::str::FromStr;
+use crate::quorum::{Quorum, Sla};
+use crate::timing::Strategy;
+
+/// Пояснение к настройкам для команды /settings
+pub const HELP: &str = "Изменить настройку можно командой '/set название значение', например '/set mode auto'.
+mode - режим модерации: manual (каждое сообщение одобряет администратор), auto (сообщения публикуются без модерации), filter (без модерации, если не сработало ни одно правило фильтрации /filters), post (сообщения публикуются сразу, а администратор может удалить их позже)
+retract - что делать, когда автор отзывает опубликованное сообщение командой /retract: ask (попросить администратора удалить его) или direct (сразу удалить из чата)
+timing - задержка доставки сообщений модератору для маскировки онлайн-активности авторов: uniform мин макс (случайная в указанных пределах), exponential среднее макс (случайная, чаще короткая), mix интервал (все сообщения доставляются вместе через равные промежутки времени) или batch количество макс (сообщения копятся, пока их не наберётся указанное количество, но не дольше макс), всё в секундах
+visibility - кто может отправлять сообщения: public (чат виден всем в списке), unlisted (чат не виден в списке, но доступен по ссылке из /invite) или invite (только получившим приглашение /invite)
+signature - подпись в конце каждой публикации, например '/set signature Прислано через {bot}', где {bot} заменяется именем бота, а {chat} - названием чата, off - без подписи
+silent - on (публиковать без звукового уведомления подписчиков) или off, автор может изменить это для своего сообщения
+comments - on (добавлять к публикации в канале кнопку перехода в группу обсуждения, если она привязана к каналу) или off
+quorum - сколько голосов модераторов нужно для решения: одобрений отклонений, например '3 2'
+sla - сроки рассмотрения в секундах: напоминание передача, например '3600 14400' - через час модераторам придёт напоминание, а через 4 часа сообщение получат запасные модераторы из /backups, 0 - шаг не нужен. Если нужно, далее окончательный срок и решение по его истечении (approve - опубликовать, reject - отклонить или expire - снять с рассмотрения), например '3600 0 86400 expire', автору сообщается о таком решении. off - без сроков";
+
+// Наибольшая длина подписи, чтобы она не съедала место, отведённое под сообщение
+const SIGNATURE_LIMIT: usize = 200;
+
+/// Режим модерации чата
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+   Manual,  // каждое сообщение одобряет администратор
+   Auto,    // сообщения публикуются без модерации
+   Filter,  // без модерации, если не сработало ни одного правила фильтрации
+   Post,    // сообщения публикуются сразу, администратор может удалить их позже
+}
+
+impl FromStr for Mode {
+   type Err = ();
+
+   fn from_str(s: &str) -> Result<Self, Self::Err> {
+      match s {
+         "manual" => Ok(Mode::Manual),
+         "auto" => Ok(Mode::Auto),
+         "filter" => Ok(Mode::Filter),
+         "post" => Ok(Mode::Post),
+         _ => Err(()),
+      }
+   }
+}
+
+impl Mode {
+   pub fn as_str(&self) -> &'static str {
+      match self {
+         Mode::Manual => "manual",
+         Mode::Auto => "auto",
+         Mode::Filter => "filter",
+         Mode::Post => "post",
+      }
+   }
+}
+
+/// Как поступать с отзывом автором опубликованного сообщения
+#[derive(Clone, Copy, PartialEq)]
+pub enum Retract {
+   Ask,     // попросить администратора удалить сообщение
+   Direct,  // удалить сообщение сразу
+}
+
+impl FromStr for Retract {
+   type Err = ();
+
+   fn from_str(s: &str) -> Result<Self, Self::Err> {
+      match s {
+         "ask" => Ok(Retract::Ask),
+         "direct" => Ok(Retract::Direct),
+         _ => Err(()),
+      }
+   }
+}
+
+impl Retract {
+   pub fn as_str(&self) -> &'static str {
+      match self {
+         Retract::Ask => "ask",
+         Retract::Direct => "direct",
+      }
+   }
+}
+
+/// Видимость чата в списке для выбора
+#[derive(Clone, Copy, PartialEq)]
+pub enum Visibility {
+   Public,   // виден всем в списке
+   Unlisted, // не виден в списке, доступен по ссылке
+   Invite,   // только для получивших приглашение
+}
+
+impl FromStr for Visibility {
+   type Err = ();
+
+   fn from_str(s: &str) -> Result<Self, Self::Err> {
+      match s {
+         "public" => Ok(Visibility::Public),
+         "unlisted" => Ok(Visibility::Unlisted),
+         "invite" => Ok(Visibility::Invite),
+         _ => Err(()),
+      }
+   }
+}
+
+impl Visibility {
+   pub fn as_str(&self) -> &'static str {
+      match self {
+         Visibility::Public => "public",
+         Visibility::Unlisted => "unlisted",
+         Visibility::Invite => "invite",
+      }
+   }
+}
+
+/// Настройки чата, хранятся в базе парами название-значение
+pub struct Settings {
+   pub mode: Mode,
+   pub retract: Retract,
+   pub timing: Strategy,
+   pub visibility: Visibility,
+   // Шаблон подписи публикаций, пустой - без подписи
+   pub signature: String,
+   // Публиковать без звукового уведомления
+   pub silent: bool,
+   // Добавлять кнопку перехода к обсуждению
+   pub comments: bool,
+   pub quorum: Quorum,
+   pub sla: Sla,
+}
+
+impl Default for Settings {
+   fn default() -> Self {
+      Self {
+         mode: Mode::Manual,
+         retract: Retract::Ask,
+         timing: Strategy::default(),
+         visibility: Visibility::Public,
+         signature: String::new(),
+         silent: false,
+         comments: false,
+         quorum: Quorum::default(),
+         sla: Sla::default(),
+      }
+   }
+}
+
+impl Settings {
+   /// Собирает настройки из сохранённых пар, неизвестное или ошибочное пропускается
+   pub fn from_pairs<I: IntoIterator<Item = (String, String)>>(pairs: I) -> Self {
+      let mut res = Self::default();
+      for (key, value) in pairs {
+         if let Err(e) = res.set(&key, &value) {
+            log::error!("Settings::from_pairs({}, {}): {}", key, value, e);
+         }
+      }
+      res
+   }
+
+   /// Изменяет настройку, при ошибке возвращает пояснение для пользователя
+   pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+      match key {
+         "mode" => {
+            self.mode = value.parse().map_err(|_| String::from("Режим модерации может быть manual, auto, filter или post"))?;
+         }
+         "retract" => {
+            self.retract = value.parse().map_err(|_| String::from("Отзыв сообщения может быть ask или direct"))?;
+         }
+         "timing" => {
+            self.timing = value.parse()?;
+         }
+         "visibility" => {
+            self.visibility = value.parse().map_err(|_| String::from("Видимость чата может быть public, unlisted или invite"))?;
+         }
+         "signature" => {
+            if value.chars().count() > SIGNATURE_LIMIT {
+               return Err(format!("Подпись должна быть не длиннее {} символов", SIGNATURE_LIMIT));
+            }
+            self.signature = if value == "off" { String::new() } else { String::from(value) };
+         }
+         "silent" => {
+            self.silent = parse_switch(value).ok_or_else(|| String::from("Публикация без звука может быть on или off"))?;
+         }
+         "comments" => {
+            self.comments = parse_switch(value).ok_or_else(|| String::from("Кнопка обсуждения может быть on или off"))?;
+         }
+         "quorum" => {
+            self.quorum = value.parse()?;
+         }
+         "sla" => {
+            self.sla = value.parse()?;
+         }
+         _ => return Err(format!("Неизвестная настройка '{}'", key)),
+      }
+      Ok(())
+   }
+
+   /// Текущие значения настроек
+   pub fn describe(&self) -> String {
+      let signature = if self.signature.is_empty() { "off" } else { &self.signature };
+      format!("mode {}\nretract {}\ntiming {}\nvisibility {}\nsignature {}\nsilent {}\ncomments {}\nquorum {}\nsla {}", self.mode.as_str(), self.retract.as_str(), self.timing, self.visibility.as_str(),
+         signature, switch_str(self.silent), switch_str(self.comments), self.quorum, self.sla)
+   }
+
+   /// Подпись для публикации в чате, пустая, если не задана
+   pub fn signature(&self, bot_name: &str, chat_name: &str) -> String {
+      self.signature.replace("{bot}", &format!("@{}", bot_name)).replace("{chat}", chat_name)
+   }
+}
+
+// Разбирает значение включено/выключено
+fn parse_switch(value: &str) -> Option<bool> {
+   match value {
+      "on" => Some(true),
+      "off" => Some(false),
+      _ => None,
+   }
+}
+
+// Значение включено/выключено для вывода
+fn switch_str(value: bool) -> &'static str {
+   if value { "on" } else { "off" }
+}
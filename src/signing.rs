@@ -0,0 +1,39 @@
+/* ===============================================================================
+Бот для анонимизации сообщений для чата.
+Подписи для входа в панель и уведомлений. 19 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+
+/// HMAC-SHA256 сообщения с указанным ключом
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+   let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC принимает ключ любой длины");
+   mac.update(message);
+   mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn hmac_rfc4231() {
+      // Тестовые примеры 1, 2, 3, 4, 6 и 7 из RFC 4231
+      let cases: [(Vec<u8>, Vec<u8>, &str); 6] = [
+         (vec![0x0b; 20], b"Hi There".to_vec(), "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"),
+         (b"Jefe".to_vec(), b"what do ya want for nothing?".to_vec(), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"),
+         (vec![0xaa; 20], vec![0xdd; 50], "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe"),
+         ((1..=25).collect(), vec![0xcd; 50], "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b"),
+         (vec![0xaa; 131], b"Test Using Larger Than Block-Size Key - Hash Key First".to_vec(), "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"),
+         (vec![0xaa; 131], b"This is a test using a larger than block-size key and a larger than block-size data. The key needs to be hashed before being used by the HMAC algorithm.".to_vec(), "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2"),
+      ];
+      for (key, data, expected) in cases.iter() {
+         assert_eq!(hex::encode(hmac_sha256(key, data)), *expected);
+      }
+   }
+}
@@ -0,0 +1,153 @@
+/* ===============================================================================
+Бот для анонимизации сообщений для чата.
+Выбор времени доставки сообщений модератору. 19 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use rand::Rng;
+use std::{fmt, str::FromStr, time::{SystemTime, UNIX_EPOCH}};
+
+// Наибольшая задержка, сутки
+const MAX_DELAY: u32 = 86400;
+
+/// Способ выбора времени доставки сообщения модератору, чтобы по нему нельзя было
+/// сопоставить сообщение с онлайн-активностью автора
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Strategy {
+   // Случайная задержка, равномерно распределённая между границами
+   Uniform { min: u32, max: u32 },
+   // Случайная задержка с экспоненциальным распределением, ограниченная сверху
+   Exponential { mean: u32, max: u32 },
+   // Все сообщения доставляются вместе через равные промежутки времени
+   Mix { interval: u32 },
+   // Сообщения копятся, пока их не наберётся нужное количество, но не дольше указанного
+   Batch { size: u32, max_wait: u32 },
+}
+
+impl Default for Strategy {
+   fn default() -> Self {
+      Strategy::Uniform { min: 3, max: 722 }
+   }
+}
+
+impl FromStr for Strategy {
+   type Err = String;
+
+   fn from_str(s: &str) -> Result<Self, Self::Err> {
+      let mut words = s.split_whitespace();
+      let name = words.next().unwrap_or_default();
+      let numbers = words.map(|w| w.parse::<u32>())
+      .collect::<Result<Vec<_>, _>>()
+      .map_err(|_| String::from("Параметры задержки должны быть целыми числами секунд"))?;
+
+      let res = match (name, numbers.as_slice()) {
+         ("uniform", &[min, max]) if min <= max => Strategy::Uniform { min, max },
+         ("exponential", &[mean, max]) if mean > 0 && mean <= max => Strategy::Exponential { mean, max },
+         ("mix", &[interval]) if interval >= 10 => Strategy::Mix { interval },
+         ("batch", &[size, max_wait]) if size >= 2 && max_wait >= 1 => Strategy::Batch { size, max_wait },
+         _ => return Err(String::from("Задержка задаётся как 'uniform мин макс', 'exponential среднее макс', 'mix интервал' (не меньше 10 сек.) или 'batch количество макс' (не меньше 2 сообщений и 1 сек.), например '/set timing uniform 3 722'")),
+      };
+
+      // Слишком долгое ожидание бессмысленно
+      match res {
+         Strategy::Uniform { max, .. } | Strategy::Exponential { max, .. } | Strategy::Batch { max_wait: max, .. } | Strategy::Mix { interval: max } if max > MAX_DELAY => {
+            Err(format!("Задержка не может превышать {} сек.", MAX_DELAY))
+         }
+         res => Ok(res),
+      }
+   }
+}
+
+impl fmt::Display for Strategy {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      match self {
+         Strategy::Uniform { min, max } => write!(f, "uniform {} {}", min, max),
+         Strategy::Exponential { mean, max } => write!(f, "exponential {} {}", mean, max),
+         Strategy::Mix { interval } => write!(f, "mix {}", interval),
+         Strategy::Batch { size, max_wait } => write!(f, "batch {} {}", size, max_wait),
+      }
+   }
+}
+
+impl Strategy {
+   /// Задержка перед доставкой в секундах, None - ждать, пока наберётся пакет
+   pub fn delay(&self) -> Option<u32> {
+      let mut rng = rand::thread_rng();
+      match *self {
+         Strategy::Uniform { min, max } => Some(rng.gen_range(min..=max)),
+         Strategy::Exponential { mean, max } => {
+            let u: f64 = rng.gen();
+            let delay = -f64::from(mean) * (1.0 - u).ln();
+            Some((delay.round() as u32).min(max))
+         }
+         Strategy::Mix { interval } => {
+            // До ближайшей общей рассылки, границы которых не зависят от сообщений
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+            let interval = u64::from(interval);
+            Some((interval - now % interval) as u32)
+         }
+         Strategy::Batch { .. } => None,
+      }
+   }
+
+   /// Когда сообщение будет доставлено, для автора
+   pub fn describe(&self, delay: Option<u32>) -> String {
+      match (self, delay) {
+         (Strategy::Mix { .. }, Some(delay)) => format!("вместе с другими сообщениями через {} сек.", delay),
+         (Strategy::Batch { size, max_wait }, _) => format!("вместе с другими сообщениями, когда их наберётся {}, но не позже чем через {} сек.", size, max_wait),
+         (_, delay) => format!("через {} сек.", delay.unwrap_or_default()),
+      }
+   }
+
+   /// Нужно ли выпустить накопленный пакет из указанного числа сообщений, старейшему из которых age сек.
+   pub fn batch_ready(&self, count: i64, age: i64) -> bool {
+      match *self {
+         Strategy::Batch { size, max_wait } => count >= i64::from(size) || age >= i64::from(max_wait),
+         // Способ могли изменить, пока сообщения ждали
+         _ => true,
+      }
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn parse_strategies() {
+      assert_eq!("uniform 3 722".parse(), Ok(Strategy::Uniform { min: 3, max: 722 }));
+      assert_eq!(" exponential  60 3600 ".parse(), Ok(Strategy::Exponential { mean: 60, max: 3600 }));
+      assert_eq!("mix 10".parse(), Ok(Strategy::Mix { interval: 10 }));
+      assert_eq!("batch 2 1".parse(), Ok(Strategy::Batch { size: 2, max_wait: 1 }));
+      // Без ожидания пакет выпускался бы сразу
+      assert!("batch 2 0".parse::<Strategy>().is_err());
+   }
+
+   #[test]
+   fn display_round_trip() {
+      for s in ["uniform 0 0", "exponential 1 86400", "mix 600", "batch 5 3600"] {
+         assert_eq!(s.parse::<Strategy>().unwrap().to_string(), s);
+      }
+   }
+
+   #[test]
+   fn invalid_strategies() {
+      for s in ["", "uniform", "uniform 10 5", "uniform 1 2 3", "uniform -1 5", "uniform a b", "exponential 0 10", "exponential 20 10",
+         "mix 9", "batch 1 60", "random 1 2", "uniform 0 86401", "mix 86401", "batch 2 86401", "exponential 1 86401"] {
+         assert!(s.parse::<Strategy>().is_err(), "{}", s);
+      }
+   }
+
+   #[test]
+   fn delays_within_bounds() {
+      for _ in 0..100 {
+         assert!(matches!(Strategy::Uniform { min: 5, max: 10 }.delay(), Some(5..=10)));
+         assert!(matches!(Strategy::Exponential { mean: 60, max: 100 }.delay(), Some(0..=100)));
+         assert!(matches!(Strategy::Mix { interval: 60 }.delay(), Some(1..=60)));
+      }
+      assert_eq!(Strategy::Batch { size: 2, max_wait: 60 }.delay(), None);
+   }
+}
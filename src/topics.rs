@@ -0,0 +1,52 @@
+/* ===============================================================================
+Бот для анонимизации сообщений для чата.
+Публикация в темы форума супергруппы. 19 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use teloxide::{
+   prelude::*,
+   requests::{Payload, ResponseResult},
+   ApiError, RequestError,
+};
+
+/// Отправляет запрос в указанную тему форума или, если тема не указана, как обычно.
+/// Библиотека о темах не знает, поэтому такой запрос уходит напрямую с добавленным
+/// полем message_thread_id
+pub async fn send<R>(requester: &AutoSend<Bot>, request: R, thread: Option<i32>) -> ResponseResult<<R::Payload as Payload>::Output>
+where
+   R: Request<Err = RequestError>,
+   R::Payload: Serialize,
+   <R::Payload as Payload>::Output: DeserializeOwned,
+{
+   let thread = match thread {
+      Some(thread) => thread,
+      None => return request.send().await,
+   };
+
+   let mut body = serde_json::to_value(request.payload_ref()).map_err(RequestError::InvalidJson)?;
+   body["message_thread_id"] = Value::from(thread);
+
+   let bot = requester.inner();
+   let mut url = bot.api_url();
+   url.set_path(&format!("bot{}/{}", bot.token(), <R::Payload as Payload>::NAME));
+
+   let response = bot.client().post(url).json(&body).send().await.map_err(RequestError::NetworkError)?;
+   let status_code = response.status();
+   let mut answer: Value = response.json().await.map_err(RequestError::NetworkError)?;
+
+   if answer["ok"].as_bool() == Some(true) {
+      serde_json::from_value(answer["result"].take()).map_err(RequestError::InvalidJson)
+   } else {
+      // Известные ошибки библиотека узнаёт по описанию
+      let description = answer["description"].take();
+      let kind = serde_json::from_value(description.clone())
+      .unwrap_or_else(|_| ApiError::Unknown(description.as_str().map(String::from).unwrap_or_default()));
+      Err(RequestError::ApiError { kind, status_code })
+   }
+}
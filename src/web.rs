@@ -0,0 +1,369 @@
+/* ===============================================================================
+Бот для анонимизации сообщений для чата.
+Веб-панель администратора чата. 19 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use std::{collections::HashMap, time::{SystemTime, UNIX_EPOCH}};
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use teloxide::{prelude::*, utils::html};
+use warp::{http::{header, Uri}, reply::Response, Filter, Rejection, Reply};
+
+use crate::database as db;
+use crate::moderation;
+use crate::settings;
+use crate::signing::hmac_sha256;
+
+// Сколько действуют данные входа через Telegram и сессия панели, сутки
+const LOGIN_TTL: u64 = 86400;
+
+// Сообщений очереди на странице
+const QUEUE_LIMIT: i64 = 50;
+
+// За сколько последних дней показывается статистика
+const STATS_DAYS: i32 = 30;
+
+// Сравнение подписей за время, не зависящее от места первого различия
+fn same(a: &str, b: &str) -> bool {
+   a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// Ссылки в HTML сообщения, адрес экранирован
+static LINKS: Lazy<Regex> = Lazy::new(|| Regex::new(r#"<a href="([^"]*)">"#).unwrap());
+
+// Оставляет в HTML сообщения только ссылки http, https и tg, у остальных убирает адрес,
+// чтобы ссылка вроде javascript: не выполнилась в панели администратора
+fn safe_links(text: &str) -> String {
+   LINKS.replace_all(text, |caps: &Captures| {
+      let url = caps[1].replace("&quot;", "\"").replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&");
+      match Url::parse(&url) {
+         Ok(url) if ["http", "https", "tg"].contains(&url.scheme()) => String::from(&caps[0]),
+         _ => String::from("<a>"),
+      }
+   }).into_owned()
+}
+
+// Секунд с начала эпохи
+fn now() -> u64 {
+   SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+// Проверяет данные, переданные виджетом входа через Telegram, и возвращает код пользователя.
+// Ключ подписи - SHA256 от токена бота, подписываются отсортированные пары без самой подписи
+fn check_login(token: &str, params: &HashMap<String, String>) -> Option<i64> {
+   let hash = params.get("hash")?;
+   let mut pairs: Vec<String> = params.iter()
+   .filter(|(key, _)| key.as_str() != "hash")
+   .map(|(key, value)| format!("{}={}", key, value))
+   .collect();
+   pairs.sort();
+
+   let secret = Sha256::digest(token.as_bytes());
+   if !same(&hex::encode(hmac_sha256(&secret, pairs.join("\n").as_bytes())), hash) {
+      return None;
+   }
+
+   // Старые данные могли утечь, например через историю браузера
+   let auth_date: u64 = params.get("auth_date")?.parse().ok()?;
+   if now().saturating_sub(auth_date) > LOGIN_TTL {
+      return None;
+   }
+   params.get("id")?.parse().ok()
+}
+
+// Подпись сессии
+fn session_signature(token: &str, data: &str) -> String {
+   hex::encode(hmac_sha256(token.as_bytes(), format!("session:{}", data).as_bytes()))
+}
+
+// Значение куки сессии: код пользователя, срок действия и подпись
+fn new_session(token: &str, user_id: i64) -> String {
+   let data = format!("{}.{}", user_id, now() + LOGIN_TTL);
+   format!("{}.{}", data, session_signature(token, &data))
+}
+
+// Проверяет куки сессии и возвращает код пользователя
+fn check_session(token: &str, cookie: &str) -> Option<i64> {
+   let (data, signature) = cookie.rsplit_once('.')?;
+   if !same(&session_signature(token, data), signature) {
+      return None;
+   }
+   let (user_id, expires) = data.split_once('.')?;
+   if expires.parse::<u64>().ok()? < now() {
+      return None;
+   }
+   user_id.parse().ok()
+}
+
+/// Маршруты веб-панели для сервера, принимающего обновления от Telegram
+pub fn routes(bot: AutoSend<Bot>) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+   let token = String::from(bot.inner().token());
+   let with_token = warp::any().map(move || token.clone());
+   let with_bot = warp::any().map(move || bot.clone());
+   let session = with_token.clone()
+   .and(warp::cookie::optional::<String>("session"))
+   .map(|token: String, cookie: Option<String>| cookie.and_then(|cookie| check_session(&token, &cookie)));
+
+   let dashboard = warp::get()
+   .and(warp::path!("admin"))
+   .and(session.clone())
+   .and(warp::query::<HashMap<String, String>>())
+   .and_then(dashboard);
+
+   let login = warp::get()
+   .and(warp::path!("admin" / "login"))
+   .and(with_token)
+   .and(warp::query::<HashMap<String, String>>())
+   .map(login);
+
+   let logout = warp::get()
+   .and(warp::path!("admin" / "logout"))
+   .map(|| with_cookie(redirect(""), "session=; Path=/admin; Max-Age=0"));
+
+   let decide = warp::post()
+   .and(warp::path!("admin" / "decide"))
+   .and(session.clone())
+   .and(with_bot)
+   .and(warp::body::form::<HashMap<String, String>>())
+   .and_then(decide);
+
+   let change_setting = warp::post()
+   .and(warp::path!("admin" / "settings"))
+   .and(session)
+   .and(warp::body::form::<HashMap<String, String>>())
+   .and_then(change_setting);
+
+   dashboard
+   .or(login).unify()
+   .or(logout).unify()
+   .or(decide).unify()
+   .or(change_setting).unify()
+}
+
+// Переход на главную страницу панели с сообщением о результате действия
+fn redirect(message: &str) -> Response {
+   let mut url = Url::parse("http://localhost/admin").unwrap();
+   if !message.is_empty() {
+      url.query_pairs_mut().append_pair("msg", message);
+   }
+   let uri = match url.query() {
+      Some(query) => format!("/admin?{}", query),
+      None => String::from("/admin"),
+   };
+   warp::redirect::see_other(uri.parse::<Uri>().unwrap()).into_response()
+}
+
+// Добавляет к ответу куки
+fn with_cookie(reply: Response, cookie: &str) -> Response {
+   warp::reply::with_header(reply, header::SET_COOKIE, cookie).into_response()
+}
+
+// Страница в HTML
+fn page(body: &str) -> Response {
+   warp::reply::html(format!("<!DOCTYPE html>
+<html lang=\"ru\">
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>{}</title>
+<style>
+body {{ font-family: sans-serif; max-width: 50em; margin: 1em auto; padding: 0 1em; }}
+.card {{ border: 1px solid #ccc; border-radius: 6px; padding: 0.5em 1em; margin: 0.5em 0; white-space: pre-wrap; }}
+.note {{ background: #eef; padding: 0.5em 1em; }}
+table {{ border-collapse: collapse; }} td, th {{ border: 1px solid #ccc; padding: 0.2em 0.6em; }}
+form {{ display: inline; }} pre {{ white-space: pre-wrap; }}
+</style>
+</head>
+<body>
+{}
+</body>
+</html>", crate::BOT_NAME, body)).into_response()
+}
+
+// Вход через Telegram: проверяем данные виджета и выдаём куки сессии
+fn login(token: String, params: HashMap<String, String>) -> Response {
+   match check_login(&token, &params) {
+      Some(user_id) => {
+         let cookie = format!("session={}; Path=/admin; Max-Age={}; HttpOnly; Secure; SameSite=Lax", new_session(&token, user_id), LOGIN_TTL);
+         with_cookie(redirect(""), &cookie)
+      }
+      None => page("<p>Не удалось проверить вход через Telegram, попробуйте ещё раз.</p><p><a href=\"/admin\">На главную</a></p>"),
+   }
+}
+
+// Страница входа с виджетом Telegram, домен сайта должен быть указан боту в @BotFather командой /setdomain
+fn login_page() -> Response {
+   page(&format!("<h1>Вход для администраторов чатов</h1>
+<script async src=\"https://telegram.org/js/telegram-widget.js?22\" data-telegram-login=\"{}\" data-size=\"large\" data-auth-url=\"/admin/login\" data-request-access=\"write\"></script>", crate::BOT_NAME))
+}
+
+// Главная страница: статистика, очередь и настройки чата администратора
+async fn dashboard(user_id: Option<i64>, query: HashMap<String, String>) -> Result<Response, Rejection> {
+   let user_id = match user_id {
+      Some(user_id) => user_id,
+      None => return Ok(login_page()),
+   };
+   let chat_name = match db::user_chat_name(user_id).await {
+      Some(chat_name) => chat_name,
+      None => return Ok(page("<p>Зарегистрированного вами чата не числится, зарегистрируйте его командой /register в боте.</p><p><a href=\"/admin/logout\">Выйти</a></p>")),
+   };
+
+   let mut body = format!("<h1>Чат {}</h1><p><a href=\"/admin/logout\">Выйти</a></p>", html::escape(&chat_name));
+   if let Some(message) = query.get("msg") {
+      body += &format!("<p class=\"note\">{}</p>", html::escape(message));
+   }
+
+   // Статистика
+   body += &format!("<h2>Статистика</h2><table><tr><th>Сообщения</th><th>За {} дней</th><th>Всего</th></tr>", STATS_DAYS);
+   for (status, recent, total) in db::stats(&chat_name, STATS_DAYS).await {
+      body += &format!("<tr><td>{}</td><td>{}</td><td>{}</td></tr>", status_name(&status), recent, total);
+   }
+   body += "</table>";
+
+   // Очередь
+   let queue = db::queue(std::slice::from_ref(&chat_name), 0, QUEUE_LIMIT).await;
+   body += &format!("<h2>Ждут решения: {}</h2>", queue.len());
+   for (submission, age) in queue {
+      let (approvals, rejections) = db::votes(submission.id).await;
+      body += &format!("<div class=\"card\">{}</div><p>Ждёт {} мин., за {}, против {}
+<form method=\"post\" action=\"/admin/decide\"><input type=\"hidden\" name=\"id\" value=\"{id}\"><input type=\"hidden\" name=\"action\" value=\"approve\"><button>🗸 Одобрить</button></form>
+<form method=\"post\" action=\"/admin/decide\"><input type=\"hidden\" name=\"id\" value=\"{id}\"><input type=\"hidden\" name=\"action\" value=\"reject\"><button>🗴 Отклонить</button></form></p>",
+         safe_links(&moderation::card_text(&submission)), age / 60, approvals, rejections, id = submission.id);
+   }
+
+   // Настройки
+   body += "<h2>Настройки</h2><table>";
+   for line in db::settings(&chat_name).await.describe().lines() {
+      let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+      body += &format!("<tr><td>{key}</td><td><form method=\"post\" action=\"/admin/settings\"><input type=\"hidden\" name=\"key\" value=\"{key}\"><input name=\"value\" value=\"{}\" size=\"40\"> <button>Сохранить</button></form></td></tr>",
+         html::escape(value), key = html::escape(key));
+   }
+   body += &format!("</table><pre>{}</pre>", html::escape(settings::HELP));
+
+   Ok(page(&body))
+}
+
+// Название состояния сообщения для статистики
+fn status_name(status: &str) -> &str {
+   match status {
+      "pending" => "ждут решения",
+      "approved" => "одобрены",
+      "rejected" => "отклонены",
+      "retracted" => "отозваны авторами",
+      "cancelled" => "отменены авторами",
+      "expired" => "сняты по сроку",
+      status => status,
+   }
+}
+
+// Решение по сообщению из очереди, голос администратора учитывается как в Telegram
+async fn decide(user_id: Option<i64>, bot: AutoSend<Bot>, form: HashMap<String, String>) -> Result<Response, Rejection> {
+   let user_id = match user_id {
+      Some(user_id) => user_id,
+      None => return Ok(redirect("")),
+   };
+   let approve = match form.get("action").map(String::as_str) {
+      Some("approve") => true,
+      Some("reject") => false,
+      _ => return Ok(redirect("Неизвестное действие")),
+   };
+   let submission = match form.get("id").and_then(|id| id.parse::<i32>().ok()) {
+      Some(id) => db::submission(id).await,
+      None => None,
+   };
+
+   // Решать можно только по сообщениям своего чата, уже направленным на модерацию
+   let chat_name = db::user_chat_name(user_id).await;
+   let (submission, card_id) = match submission {
+      Some(submission) if Some(&submission.chat_name) == chat_name.as_ref() => match submission.card_id {
+         Some(card_id) => (submission, card_id),
+         None => return Ok(redirect("Сообщение ещё не направлено на модерацию")),
+      },
+      _ => return Ok(redirect("Сообщение не найдено")),
+   };
+
+   let by = format!("администратор через веб-панель, {}", chrono::Utc::now().format("%d.%m.%Y %H:%M UTC"));
+   let card = (submission.card_chat.unwrap_or(user_id), card_id);
+   let res = moderation::cast_vote(&bot, user_id, card, &submission, user_id, approve, by).await;
+   Ok(redirect(&res))
+}
+
+// Изменение настройки чата
+async fn change_setting(user_id: Option<i64>, form: HashMap<String, String>) -> Result<Response, Rejection> {
+   let chat_name = match user_id {
+      Some(user_id) => db::user_chat_name(user_id).await,
+      None => return Ok(redirect("")),
+   };
+   let chat_name = match chat_name {
+      Some(chat_name) => chat_name,
+      None => return Ok(redirect("Зарегистрированного вами чата не числится")),
+   };
+   let key = form.get("key").map(String::as_str).unwrap_or_default();
+   let value = form.get("value").map(|value| value.trim()).unwrap_or_default();
+
+   // Проверим значение, прежде чем сохранять
+   let mut settings = db::settings(&chat_name).await;
+   if let Err(e) = settings.set(key, value) {
+      return Ok(redirect(&e));
+   }
+   if db::set_setting(&chat_name, key, value).await {
+      Ok(redirect(&format!("Настройка {} сохранена", key)))
+   } else {
+      Ok(redirect("Не удалось сохранить настройку"))
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn login(auth_date: &str, hash: &str) -> HashMap<String, String> {
+      [("id", "42"), ("first_name", "Иван"), ("username", "ivan"), ("auth_date", auth_date), ("hash", hash)].iter()
+      .map(|(key, value)| (String::from(*key), String::from(*value)))
+      .collect()
+   }
+
+   const TOKEN: &str = "123456:ABC-DEF1234ghIkl-zyx57W2v1u123ew11";
+
+   #[test]
+   fn login_widget() {
+      // Подпись посчитана отдельно по описанию Telegram, дата входа в будущем, чтобы не устаревала
+      let params = login("4102444800", "84f8aad7c4e0dbc7aefa6ad5a0e072b961e9e86bce6c444871cb1181a372e3b6");
+      assert_eq!(check_login(TOKEN, &params), Some(42));
+
+      // Чужой токен или изменённые данные
+      assert_eq!(check_login("654321:other", &params), None);
+      let mut forged = params.clone();
+      forged.insert(String::from("id"), String::from("43"));
+      assert_eq!(check_login(TOKEN, &forged), None);
+      let mut unsigned = params;
+      unsigned.remove("hash");
+      assert_eq!(check_login(TOKEN, &unsigned), None);
+
+      // Верная подпись, но данные устарели
+      assert_eq!(check_login(TOKEN, &login("1600000000", "6aa29b734b9b945eff355c0d454964a7581068ef8fffdfcd1bad1902ba130941")), None);
+   }
+
+   #[test]
+   fn session_cookie() {
+      let cookie = new_session(TOKEN, 42);
+      assert_eq!(check_session(TOKEN, &cookie), Some(42));
+      assert_eq!(check_session("654321:other", &cookie), None);
+      assert_eq!(check_session(TOKEN, &cookie.replacen("42", "43", 1)), None);
+   }
+
+   #[test]
+   fn only_safe_links() {
+      assert_eq!(safe_links(r#"<a href="https://example.com/?a=1&amp;b=2">x</a>"#), r#"<a href="https://example.com/?a=1&amp;b=2">x</a>"#);
+      assert_eq!(safe_links(r#"<a href="tg://user?id=42">x</a>"#), r#"<a href="tg://user?id=42">x</a>"#);
+      for url in ["javascript:alert(1)", "JavaScript:alert(1)", "java\tscript:alert(1)", "data:text/html,x", "vbscript:x", "/relative", ""] {
+         assert_eq!(safe_links(&format!(r#"<b><a href="{}">x</a></b>"#, url)), "<b><a>x</a></b>", "{}", url);
+      }
+   }
+}